@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use berechenbarkeit_lib::vendors::xml::{is_xml, parse_xml};
+use berechenbarkeit_lib::{extract_text, parse_pdf, vendors, Cli, Invoice, InvoiceVendor, OutputFormat};
+use clap::Parser;
+
+fn parse_file(path: &Path, cli: &Cli) -> anyhow::Result<Invoice> {
+    let pdf = fs::read(path)?;
+    if is_xml(&pdf) {
+        return Ok(parse_xml(&String::from_utf8(pdf)?)?);
+    }
+    if cli.dump_text {
+        println!("{}", extract_text(&pdf)?);
+    }
+    let vendor = cli.vendor.as_ref().map(|vendor| InvoiceVendor::try_from(vendor.to_lowercase())).transpose()?;
+    Ok(parse_pdf(&pdf, vendor)?)
+}
+
+fn print_table(invoice: &Invoice) {
+    println!(
+        "{} – {} – {} – {} EUR (Brutto) – {}",
+        invoice.vendor,
+        invoice.meta.invoice_number,
+        invoice.meta.date,
+        invoice.meta.sum_gross,
+        invoice.meta.payment_type.map_or_else(|| "Zahlungsart unbekannt".to_string(), |p| p.to_string())
+    );
+    println!("Abgleich: {}", invoice.validation.status);
+    for check in &invoice.validation.vat_rates {
+        println!(
+            "  MwSt {}: Netto {} (Positionen {}) – {}",
+            check.vat,
+            check.printed_net.as_ref().map_or_else(|| "–".to_string(), |net| net.to_string()),
+            check.items_net,
+            check.status
+        );
+    }
+    println!(
+        "{:>10} {:<16} {:<50} {:>10} {:>12} {:>6} {:>12}",
+        "Pos", "Art.-Nr.", "Beschreibung", "Menge", "Einzel Netto", "MwSt", "Gesamt Netto"
+    );
+    for item in &invoice.items {
+        println!(
+            "{:>10} {:<16} {:<50} {:>10} {:>12} {:>6} {:>12}",
+            item.pos,
+            item.article_number,
+            item.description.trim(),
+            item.amount,
+            item.net_price_single,
+            item.vat,
+            item.net_total_price
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Err(e) = vendors::init_regex_vendors(cli.vendor_definitions_dir.as_deref()) {
+        eprintln!("error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in &cli.paths {
+        match parse_file(path, &cli) {
+            Ok(invoice) => match cli.format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&invoice).unwrap()),
+                OutputFormat::Table => print_table(&invoice),
+            },
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    exit_code
+}
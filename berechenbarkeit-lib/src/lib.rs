@@ -1,47 +1,65 @@
-use std::{
-    fmt,
-    num::{ParseFloatError, ParseIntError},
-    path::PathBuf,
-};
-
-use clap::Parser;
-use once_cell::sync::Lazy;
-use serde::Serialize;
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
+use std::{fmt, path::PathBuf};
+
+use bigdecimal::BigDecimal;
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use time::{error::ComponentRange, PrimitiveDateTime};
-use vendors::regex::{BAUHAUS, IKEA, KOKKU, MEDICALCORNER, METRO, MOLTONDISCOUNT, ROHALM};
+use time::PrimitiveDateTime;
+use validation::{ValidationReport, VatSubtotal};
+use vendors::regex_vendors;
 
+pub mod validation;
 pub mod vendors;
 
 #[derive(Debug, Error, PartialEq)]
 pub enum InvoiceParseError {
-    #[error("Failed to parse floating point number on invoice: {0}")]
-    ParseFloatError(#[from] ParseFloatError),
-    #[error("Failed to parse integer number on invoice: {0}")]
-    ParseIntError(#[from] ParseIntError),
-    #[error("Unparseable date: {0}")]
-    DateError(#[from] ComponentRange),
+    #[error("Invalid value '{1}' for field {0} on line {2}: {3}")]
+    InvalidFieldError(String, String, usize, String),
     #[error("Required field {0} not found on invoice")]
     FieldMissingError(String),
-    #[error("Unrecognized VAT class '{0}'")]
-    UnrecognizedVatClass(String),
+    #[error("Required field {0} not found in invoice item on line {1}")]
+    ItemFieldMissingError(String, usize),
+    #[error("Unrecognized VAT class '{0}' on line {1}")]
+    UnrecognizedVatClass(String, usize),
+    #[error("Required element {0} not found in the XML element on line {1}")]
+    XmlElementMissingError(String, usize),
+    #[error("Invalid XML invoice: {0}")]
+    InvalidXmlError(String),
+    #[error("Failed to read PDF: {0}")]
+    PdfError(String),
     #[error("Unknown vendor: '{0}'")]
     UnknownVendorError(String),
+    #[error("Could not detect the vendor of this invoice, please select it manually")]
+    VendorNotDetectedError,
+    #[error("Could not detect the vendor of this invoice unambiguously, candidates: {}", .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))]
+    AmbiguousVendorError(Vec<InvoiceVendor>),
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+    MultipleErrors(Vec<InvoiceParseError>),
 }
 
-#[derive(Debug, Clone, Serialize, EnumIter)]
-pub enum InvoiceVendor {
-    Metro,
-    Bauhaus,
-    Ikea,
-    MedicalCorner,
-    MoltonDiscount,
-    Kokku,
-    Rohalm,
+impl InvoiceParseError {
+    /// Combines the problems found in one document into a single error
+    pub fn from_list(errors: Vec<InvoiceParseError>) -> Self {
+        let mut errors: Vec<InvoiceParseError> = errors.into_iter().flat_map(InvoiceParseError::into_list).collect();
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => InvoiceParseError::MultipleErrors(errors),
+        }
+    }
+
+    /// The individual problems, one per entry
+    pub fn into_list(self) -> Vec<InvoiceParseError> {
+        match self {
+            InvoiceParseError::MultipleErrors(errors) => errors,
+            e => vec![e],
+        }
+    }
 }
 
+/// Name of one of the vendors loaded by [`vendors::init_regex_vendors`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoiceVendor(String);
+
 #[derive(Debug, Clone, Serialize)]
 pub enum InvoiceItemType {
     Expense,
@@ -49,7 +67,7 @@ pub enum InvoiceItemType {
 }
 
 pub enum InvoiceParser {
-    Regex(&'static Lazy<vendors::regex::RegexVendor>),
+    Regex(&'static vendors::regex::RegexVendor),
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -57,19 +75,72 @@ pub struct InvoiceItem {
     pub typ: InvoiceItemType,
     pub pos: u32,
     pub article_number: String,
+    pub ean: Option<String>,
     pub description: String,
-    pub net_price_single: f64,
-    pub vat: f64,
-    pub amount: f64,
-    pub net_total_price: f64,
+    pub net_price_single: BigDecimal,
+    pub vat: BigDecimal,
+    pub amount: BigDecimal,
+    pub net_total_price: BigDecimal,
+    /// Number of pieces per packaging unit, if the invoice lists one; `amount` is already given in pieces
+    pub packaging_unit_amount: Option<BigDecimal>,
+}
+
+/// How an invoice was paid, the wording of each vendor is mapped to these in its vendor definition
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentType {
+    Cash,
+    DebitCard,
+    CreditCard,
+    BankTransfer,
+    OnAccount,
+}
+
+impl PaymentType {
+    pub const ALL: [PaymentType; 5] = [
+        PaymentType::Cash,
+        PaymentType::DebitCard,
+        PaymentType::CreditCard,
+        PaymentType::BankTransfer,
+        PaymentType::OnAccount,
+    ];
+
+    /// Identifier used in the database and in exports
+    pub fn key(&self) -> &'static str {
+        match self {
+            PaymentType::Cash => "cash",
+            PaymentType::DebitCard => "debit_card",
+            PaymentType::CreditCard => "credit_card",
+            PaymentType::BankTransfer => "bank_transfer",
+            PaymentType::OnAccount => "on_account",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<PaymentType> {
+        PaymentType::ALL.into_iter().find(|p| p.key() == key)
+    }
+}
+
+impl fmt::Display for PaymentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PaymentType::Cash => "Bar",
+            PaymentType::DebitCard => "EC-Karte",
+            PaymentType::CreditCard => "Kreditkarte",
+            PaymentType::BankTransfer => "Überweisung",
+            PaymentType::OnAccount => "Auf Rechnung",
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct InvoiceMeta {
     pub invoice_number: String,
-    pub sum_gross: f64,
-    pub payment_type: Option<String>,
+    pub sum_gross: BigDecimal,
+    pub payment_type: Option<PaymentType>,
     pub date: PrimitiveDateTime,
+    /// Per VAT rate subtotals from the invoice footer
+    pub vat_subtotals: Vec<VatSubtotal>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -77,62 +148,146 @@ pub struct Invoice {
     pub vendor: InvoiceVendor,
     pub meta: InvoiceMeta,
     pub items: Vec<InvoiceItem>,
+    pub validation: ValidationReport,
 }
 
+/// Parse invoice PDFs without running the web application
 #[derive(Parser)]
-struct Cli {
-    /// Path to PDF file
-    path: PathBuf,
+#[command(name = "berechenbarkeit-parse")]
+pub struct Cli {
+    /// Path to PDF or XRechnung XML file(s)
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Vendor of the invoices, detected automatically if not given
+    #[arg(long)]
+    pub vendor: Option<String>,
+
+    /// Output format of the parsed invoices
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+
+    /// Print the text extracted from the PDF before parsing it
+    #[arg(long)]
+    pub dump_text: bool,
+
+    /// Directory with additional vendor definition files
+    #[arg(long)]
+    pub vendor_definitions_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Table,
 }
 
 pub trait Vendor {
     fn extract_invoice_data(&self, pdf: &[u8], vendor: InvoiceVendor) -> anyhow::Result<Invoice>;
+
+    fn extract_invoice_data_from_text(&self, invoice_text: &str, vendor: InvoiceVendor) -> Result<Invoice, InvoiceParseError>;
+
+    /// Rates how well this parser matches the extracted invoice text, 0 meaning it can't parse it at all.
+    fn match_score(&self, invoice_text: &str) -> u32;
 }
 
 impl fmt::Display for InvoiceVendor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Metro => write!(f, "Metro"),
-            Self::Bauhaus => write!(f, "Bauhaus"),
-            Self::Ikea => write!(f, "IKEA"),
-            Self::MedicalCorner => write!(f, "MedicalCorner"),
-            Self::MoltonDiscount => write!(f, "MoltonDiscount"),
-            Self::Kokku => write!(f, "Kokku"),
-            Self::Rohalm => write!(f, "Rohalm"),
-        }
+        write!(f, "{}", self.0)
+    }
+}
+
+impl InvoiceVendor {
+    /// Vendor of an invoice that names its seller, known vendors keep the name of their definition
+    pub fn from_seller_name(seller: &str) -> InvoiceVendor {
+        let seller_lowercase = seller.to_lowercase();
+        regex_vendors()
+            .iter()
+            .find(|vendor| seller_lowercase.contains(&vendor.name.to_lowercase()))
+            .map_or_else(|| InvoiceVendor(seller.trim().to_string()), |vendor| InvoiceVendor(vendor.name.clone()))
     }
 }
 
 impl TryFrom<String> for InvoiceVendor {
     type Error = crate::InvoiceParseError;
     fn try_from(e: String) -> Result<Self, Self::Error> {
-        InvoiceVendor::iter()
-            .find(|vendor| vendor.to_string().to_lowercase() == e)
+        regex_vendors()
+            .iter()
+            .find(|vendor| vendor.name.to_lowercase() == e.to_lowercase())
+            .map(|vendor| InvoiceVendor(vendor.name.clone()))
             .ok_or(InvoiceParseError::UnknownVendorError(e))
     }
 }
 
-impl From<InvoiceVendor> for InvoiceParser {
-    fn from(e: InvoiceVendor) -> Self {
-        match e {
-            InvoiceVendor::Metro => InvoiceParser::Regex(&METRO),
-            InvoiceVendor::Bauhaus => InvoiceParser::Regex(&BAUHAUS),
-            InvoiceVendor::Ikea => InvoiceParser::Regex(&IKEA),
-            InvoiceVendor::MedicalCorner => InvoiceParser::Regex(&MEDICALCORNER),
-            InvoiceVendor::MoltonDiscount => InvoiceParser::Regex(&MOLTONDISCOUNT),
-            InvoiceVendor::Kokku => InvoiceParser::Regex(&KOKKU),
-            InvoiceVendor::Rohalm => InvoiceParser::Regex(&ROHALM),
-        }
+impl TryFrom<InvoiceVendor> for InvoiceParser {
+    type Error = crate::InvoiceParseError;
+    fn try_from(e: InvoiceVendor) -> Result<Self, Self::Error> {
+        regex_vendors()
+            .iter()
+            .find(|vendor| vendor.name == e.0)
+            .map(InvoiceParser::Regex)
+            .ok_or(InvoiceParseError::UnknownVendorError(e.0))
     }
 }
 
 pub fn get_vendors() -> Vec<String> {
-    InvoiceVendor::iter().map(|vendor| vendor.to_string()).collect()
+    regex_vendors().iter().map(|vendor| vendor.name.clone()).collect()
 }
 
 pub fn get_parser_for_vendor(vendor: Option<InvoiceVendor>) -> Option<InvoiceParser> {
-    match vendor {
-        Some(v) => Some(InvoiceParser::from(v)),
-        None => None,
+    vendor.and_then(|v| InvoiceParser::try_from(v).ok())
+}
+
+impl InvoiceParser {
+    pub fn match_score(&self, invoice_text: &str) -> u32 {
+        match self {
+            InvoiceParser::Regex(p) => p.match_score(invoice_text),
+        }
+    }
+
+    pub fn extract_invoice_data_from_text(&self, invoice_text: &str) -> Result<Invoice, InvoiceParseError> {
+        match self {
+            InvoiceParser::Regex(p) => p.extract_invoice_data_from_text(invoice_text, InvoiceVendor(p.name.clone())),
+        }
+    }
+}
+
+pub fn extract_text(pdf: &[u8]) -> anyhow::Result<String> {
+    Ok(pdf_extract::extract_text_from_mem(pdf)?)
+}
+
+/// Parses a PDF invoice. An embedded ZUGFeRD / Factur-X XML is used for every vendor, otherwise the text is read
+/// with the parser of `vendor`, or of the detected vendor if it is `None`.
+pub fn parse_pdf(pdf: &[u8], vendor: Option<InvoiceVendor>) -> Result<Invoice, InvoiceParseError> {
+    if let Some(invoice) = vendors::facturx::extract_invoice_data(pdf) {
+        return invoice;
+    }
+    let invoice_text = extract_text(pdf).map_err(|e| InvoiceParseError::PdfError(e.to_string()))?;
+    let vendor = match vendor {
+        Some(vendor) => vendor,
+        None => detect_vendor_from_text(&invoice_text)?,
+    };
+    InvoiceParser::try_from(vendor)?.extract_invoice_data_from_text(&invoice_text)
+}
+
+/// Detects the vendor of a PDF invoice by picking the parser with the best match score.
+pub fn detect_vendor(pdf: &[u8]) -> anyhow::Result<InvoiceVendor> {
+    let text = extract_text(pdf)?;
+    Ok(detect_vendor_from_text(&text)?)
+}
+
+pub fn detect_vendor_from_text(invoice_text: &str) -> Result<InvoiceVendor, InvoiceParseError> {
+    let scores: Vec<(InvoiceVendor, u32)> = regex_vendors()
+        .iter()
+        .map(|vendor| (InvoiceVendor(vendor.name.clone()), InvoiceParser::Regex(vendor).match_score(invoice_text)))
+        .collect();
+    let best_score = scores.iter().map(|(_, score)| *score).max().unwrap_or(0);
+    if best_score == 0 {
+        return Err(InvoiceParseError::VendorNotDetectedError);
+    }
+    let mut candidates: Vec<InvoiceVendor> = scores.into_iter().filter(|(_, score)| *score == best_score).map(|(vendor, _)| vendor).collect();
+    match candidates.len() {
+        1 => Ok(candidates.remove(0)),
+        _ => Err(InvoiceParseError::AmbiguousVendorError(candidates)),
     }
 }
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bigdecimal::{BigDecimal, One, Zero};
+use serde::Serialize;
+
+use crate::{InvoiceItem, InvoiceMeta};
+
+/// Subtotal of one VAT rate as printed in the footer of an invoice
+#[derive(Debug, Clone, Serialize)]
+pub struct VatSubtotal {
+    pub vat: BigDecimal,
+    pub net: BigDecimal,
+    pub vat_amount: Option<BigDecimal>,
+}
+
+/// Result of comparing a printed total with the sum of the parsed items, ordered from good to bad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStatus {
+    Matched,
+    /// The difference is small enough to be caused by rounding each item
+    RoundingDifference,
+    /// Items are missing or were parsed wrongly
+    MissingItems,
+}
+
+impl ValidationStatus {
+    /// Identifier used in the database
+    pub fn key(&self) -> &'static str {
+        match self {
+            ValidationStatus::Matched => "matched",
+            ValidationStatus::RoundingDifference => "rounding_difference",
+            ValidationStatus::MissingItems => "missing_items",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<ValidationStatus> {
+        [ValidationStatus::Matched, ValidationStatus::RoundingDifference, ValidationStatus::MissingItems]
+            .into_iter()
+            .find(|s| s.key() == key)
+    }
+
+    /// Compares a printed total with the one calculated from `item_count` items
+    fn of(printed: &BigDecimal, calculated: &BigDecimal, item_count: usize) -> ValidationStatus {
+        let difference = (printed - calculated).abs();
+        // Every item may have been rounded to the cent on its own
+        let tolerance = BigDecimal::new(1.into(), 2) * BigDecimal::from(item_count.max(1) as u64);
+        if difference.is_zero() {
+            ValidationStatus::Matched
+        } else if difference <= tolerance {
+            ValidationStatus::RoundingDifference
+        } else {
+            ValidationStatus::MissingItems
+        }
+    }
+}
+
+impl fmt::Display for ValidationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ValidationStatus::Matched => "Stimmt überein",
+            ValidationStatus::RoundingDifference => "Rundungsdifferenz",
+            ValidationStatus::MissingItems => "Positionen fehlen oder wurden falsch erkannt",
+        })
+    }
+}
+
+/// Comparison of one VAT rate's footer subtotal with the items of that rate
+#[derive(Debug, Clone, Serialize)]
+pub struct VatRateCheck {
+    pub vat: BigDecimal,
+    pub printed_net: Option<BigDecimal>,
+    pub items_net: BigDecimal,
+    pub printed_vat_amount: Option<BigDecimal>,
+    pub items_vat_amount: BigDecimal,
+    pub status: ValidationStatus,
+}
+
+/// Cross-check of the totals printed on an invoice against its parsed items
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    /// Worst status of all checks
+    pub status: ValidationStatus,
+    pub printed_gross: BigDecimal,
+    pub items_gross: BigDecimal,
+    pub gross_status: ValidationStatus,
+    /// Empty if the invoice has no per VAT rate subtotals or the vendor definition doesn't know them
+    pub vat_rates: Vec<VatRateCheck>,
+    /// Parts of the invoice that could not be read and are missing from the checks
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn new(meta: &InvoiceMeta, items: &[InvoiceItem]) -> ValidationReport {
+        let mut by_rate: BTreeMap<BigDecimal, (BigDecimal, usize)> = BTreeMap::new();
+        for item in items {
+            let (net, count) = by_rate.entry(item.vat.clone()).or_insert_with(|| (BigDecimal::zero(), 0));
+            *net += &item.net_total_price;
+            *count += 1;
+        }
+        let items_gross = by_rate.iter().map(|(vat, (net, _))| net * (BigDecimal::one() + vat)).sum::<BigDecimal>().round(2);
+        let gross_status = ValidationStatus::of(&meta.sum_gross, &items_gross, items.len());
+
+        let mut vat_rates: Vec<VatRateCheck> = vec![];
+        if !meta.vat_subtotals.is_empty() {
+            for subtotal in &meta.vat_subtotals {
+                by_rate.entry(subtotal.vat.clone()).or_insert_with(|| (BigDecimal::zero(), 0));
+            }
+            for (vat, (net, count)) in by_rate {
+                let subtotal = meta.vat_subtotals.iter().find(|s| s.vat == vat);
+                let items_net = net.round(2);
+                let items_vat_amount = (&net * &vat).round(2);
+                let status = match subtotal {
+                    Some(subtotal) => {
+                        let net_status = ValidationStatus::of(&subtotal.net, &items_net, count);
+                        match &subtotal.vat_amount {
+                            Some(vat_amount) => net_status.max(ValidationStatus::of(vat_amount, &items_vat_amount, count)),
+                            None => net_status,
+                        }
+                    }
+                    // Items of a rate the footer doesn't list
+                    None => ValidationStatus::MissingItems,
+                };
+                vat_rates.push(VatRateCheck {
+                    vat,
+                    printed_net: subtotal.map(|s| s.net.clone()),
+                    items_net,
+                    printed_vat_amount: subtotal.and_then(|s| s.vat_amount.clone()),
+                    items_vat_amount,
+                    status,
+                });
+            }
+        }
+
+        ValidationReport {
+            status: vat_rates.iter().map(|r| r.status).fold(gross_status, ValidationStatus::max),
+            printed_gross: meta.sum_gross.clone(),
+            items_gross,
+            gross_status,
+            vat_rates,
+            problems: vec![],
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! UN/CEFACT Cross Industry Invoice (CII), the XML syntax of ZUGFeRD / Factur-X and one of the two XRechnung syntaxes
+
+use bigdecimal::{BigDecimal, One, Zero};
+use roxmltree::{Document, Node};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use super::xml::{children, document, payment_type_from_code, Xml};
+use crate::validation::{ValidationReport, VatSubtotal};
+use crate::{Invoice, InvoiceItem, InvoiceItemType, InvoiceMeta, InvoiceParseError, InvoiceVendor};
+
+pub fn parse_cii(xml: &str) -> Result<Invoice, InvoiceParseError> {
+    from_document(&document(xml)?)
+}
+
+pub(crate) fn from_document(doc: &Document) -> Result<Invoice, InvoiceParseError> {
+    let xml = Xml { doc };
+    let root = doc.root_element();
+    if root.tag_name().name() != "CrossIndustryInvoice" {
+        return Err(InvoiceParseError::InvalidXmlError(format!("unexpected root element {}", root.tag_name().name())));
+    }
+    let document = xml.required(root, "ExchangedDocument")?;
+    let transaction = xml.required(root, "SupplyChainTradeTransaction")?;
+    let settlement = xml.required(transaction, "ApplicableHeaderTradeSettlement")?;
+    // Credit notes list positive amounts, we store them as negative ones
+    let sign = match xml.optional_text(document, "TypeCode") {
+        Some("381") => -BigDecimal::one(),
+        _ => BigDecimal::one(),
+    };
+
+    let mut errors: Vec<InvoiceParseError> = vec![];
+    let mut items: Vec<InvoiceItem> = vec![];
+    for (i, line) in children(transaction, "IncludedSupplyChainTradeLineItem").enumerate() {
+        match xml.line_item(line, i as u32 + 1, &sign) {
+            Ok(item) => items.push(item),
+            Err(e) => errors.push(e),
+        }
+    }
+    for allowance_charge in children(settlement, "SpecifiedTradeAllowanceCharge") {
+        match xml.allowance_charge(allowance_charge, &sign) {
+            Ok(item) => items.push(item),
+            Err(e) => errors.push(e),
+        }
+    }
+    let meta = xml.meta(document, settlement, &sign);
+    let seller = xml.text(transaction, "ApplicableHeaderTradeAgreement/SellerTradeParty/Name");
+
+    match (meta, seller, errors.is_empty()) {
+        (Ok(meta), Ok(seller), true) => Ok(Invoice {
+            vendor: InvoiceVendor::from_seller_name(seller),
+            validation: ValidationReport::new(&meta, &items),
+            meta,
+            items,
+        }),
+        (meta, seller, _) => {
+            errors.extend([meta.err(), seller.err()].into_iter().flatten());
+            Err(InvoiceParseError::from_list(errors))
+        }
+    }
+}
+
+/// CII specific lookups
+impl<'a, 'input> Xml<'a, 'input> {
+    /// VAT rate of a CII `ApplicableTradeTax` element, categories like exempt (E) or zero rated (Z) have no percentage
+    fn vat_rate(&self, tax: Node<'a, 'input>) -> Result<BigDecimal, InvoiceParseError> {
+        Ok(self.optional_decimal(tax, "RateApplicablePercent")?.unwrap_or_else(BigDecimal::zero) / BigDecimal::from(100))
+    }
+
+    fn date(&self, node: Node<'a, 'input>, path: &str) -> Result<PrimitiveDateTime, InvoiceParseError> {
+        let element = self.required(node, path)?;
+        let text = element.text().unwrap_or("").trim();
+        let invalid_date = |reason: String| InvoiceParseError::InvalidFieldError(path.to_string(), text.to_string(), self.line(element), reason);
+        // Format 102 is the only one CII invoices use: YYYYMMDD
+        let part = |range: std::ops::Range<usize>| -> Result<u16, InvoiceParseError> {
+            text.get(range)
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| invalid_date("expected a date like 20240524".to_string()))
+        };
+        if text.len() != 8 {
+            return Err(invalid_date("expected a date like 20240524".to_string()));
+        }
+        let month = Month::try_from(part(4..6)? as u8).map_err(|e| invalid_date(e.to_string()))?;
+        let date = Date::from_calendar_date(part(0..4)?.into(), month, part(6..8)? as u8).map_err(|e| invalid_date(e.to_string()))?;
+        Ok(PrimitiveDateTime::new(date, Time::MIDNIGHT))
+    }
+
+    fn meta(&self, document: Node<'a, 'input>, settlement: Node<'a, 'input>, sign: &BigDecimal) -> Result<InvoiceMeta, InvoiceParseError> {
+        let vat_subtotals = children(settlement, "ApplicableTradeTax")
+            .map(|tax| {
+                Ok(VatSubtotal {
+                    vat: self.vat_rate(tax)?,
+                    net: self.decimal(tax, "BasisAmount")? * sign,
+                    vat_amount: self.optional_decimal(tax, "CalculatedAmount")?.map(|amount| amount * sign),
+                })
+            })
+            .collect::<Result<Vec<VatSubtotal>, InvoiceParseError>>()?;
+        Ok(InvoiceMeta {
+            invoice_number: self.text(document, "ID")?.to_string(),
+            sum_gross: self.decimal(settlement, "SpecifiedTradeSettlementHeaderMonetarySummation/GrandTotalAmount")? * sign,
+            payment_type: children(settlement, "SpecifiedTradeSettlementPaymentMeans").find_map(|means| self.optional_text(means, "TypeCode").and_then(payment_type_from_code)),
+            date: self.date(document, "IssueDateTime/DateTimeString")?,
+            vat_subtotals,
+        })
+    }
+
+    fn line_item(&self, line: Node<'a, 'input>, counter: u32, sign: &BigDecimal) -> Result<InvoiceItem, InvoiceParseError> {
+        let product = self.required(line, "SpecifiedTradeProduct")?;
+        let agreement = self.required(line, "SpecifiedLineTradeAgreement")?;
+        let line_settlement = self.required(line, "SpecifiedLineTradeSettlement")?;
+
+        let net_price_single = self.unit_price(agreement, "NetPriceProductTradePrice/ChargeAmount", "NetPriceProductTradePrice/BasisQuantity")?;
+        let amount = self.decimal(line, "SpecifiedLineTradeDelivery/BilledQuantity")? * sign;
+
+        Ok(InvoiceItem {
+            typ: match (&net_price_single * &amount) >= BigDecimal::zero() {
+                true => InvoiceItemType::Expense,
+                false => InvoiceItemType::Credit,
+            },
+            // Line ids don't have to be numeric
+            pos: self
+                .optional_text(line, "AssociatedDocumentLineDocument/LineID")
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(counter),
+            article_number: self.optional_text(product, "SellerAssignedID").unwrap_or("").to_string(),
+            ean: children(product, "GlobalID")
+                .find(|id| id.attribute("schemeID") == Some("0160"))
+                .and_then(|id| id.text())
+                .map(|ean| ean.trim().to_string()),
+            description: self.text(product, "Name")?.to_string(),
+            net_price_single,
+            vat: self.vat_rate(self.required(line_settlement, "ApplicableTradeTax")?)?,
+            amount,
+            net_total_price: self.decimal(line_settlement, "SpecifiedTradeSettlementLineMonetarySummation/LineTotalAmount")? * sign,
+            packaging_unit_amount: None,
+        })
+    }
+
+    /// Document level allowance (e.g. a discount) or charge (e.g. shipping), stored as an item of its own
+    fn allowance_charge(&self, allowance_charge: Node<'a, 'input>, sign: &BigDecimal) -> Result<InvoiceItem, InvoiceParseError> {
+        let is_charge = self.text(allowance_charge, "ChargeIndicator/Indicator")? == "true";
+        let amount = self.decimal(allowance_charge, "ActualAmount")? * sign;
+        let net = match is_charge {
+            true => amount,
+            false => -amount,
+        };
+        Ok(InvoiceItem {
+            typ: match net >= BigDecimal::zero() {
+                true => InvoiceItemType::Expense,
+                false => InvoiceItemType::Credit,
+            },
+            pos: u32::MAX,
+            article_number: "".to_string(),
+            ean: None,
+            description: match self.optional_text(allowance_charge, "Reason") {
+                Some(reason) => reason.to_string(),
+                None if is_charge => "Zuschlag".to_string(),
+                None => "Nachlass".to_string(),
+            },
+            net_price_single: net.clone(),
+            vat: self.vat_rate(self.required(allowance_charge, "CategoryTradeTax")?)?,
+            amount: BigDecimal::one(),
+            net_total_price: net,
+            packaging_unit_amount: None,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+//! ZUGFeRD / Factur-X: PDF/A-3 invoices carrying their data as an embedded CII XML file
+
+use lopdf::{Dictionary, Document, Object};
+
+use crate::vendors::cii::parse_cii;
+use crate::{Invoice, InvoiceParseError};
+
+/// File names the standards (and their older versions) use for the embedded invoice
+const ATTACHMENT_NAMES: &[&str] = &["factur-x.xml", "zugferd-invoice.xml", "xrechnung.xml"];
+
+/// Parses the invoice embedded in a ZUGFeRD / Factur-X PDF, the vendor is the seller named in it. `None` for every other PDF
+pub fn extract_invoice_data(pdf: &[u8]) -> Option<Result<Invoice, InvoiceParseError>> {
+    embedded_invoice_xml(pdf).map(|xml| parse_cii(&xml))
+}
+
+/// The embedded invoice XML of a ZUGFeRD / Factur-X PDF, `None` for every other PDF
+pub fn embedded_invoice_xml(pdf: &[u8]) -> Option<String> {
+    let document = Document::load_mem(pdf).ok()?;
+    document
+        .objects
+        .values()
+        .filter_map(|object| object.as_dict().ok())
+        .filter(|dict| is_invoice_attachment(dict))
+        .find_map(|filespec| {
+            let (_, embedded_files) = document.dereference(filespec.get(b"EF").ok()?).ok()?;
+            let embedded_files = embedded_files.as_dict().ok()?;
+            let (_, stream) = document.dereference(embedded_files.get(b"F").or_else(|_| embedded_files.get(b"UF")).ok()?).ok()?;
+            String::from_utf8(stream.as_stream().ok()?.get_plain_content().ok()?).ok()
+        })
+}
+
+fn is_invoice_attachment(dict: &Dictionary) -> bool {
+    dict.has(b"EF")
+        && [b"UF".as_slice(), b"F".as_slice()]
+            .into_iter()
+            .filter_map(|key| dict.get(key).ok())
+            .filter_map(file_name)
+            .any(|name| ATTACHMENT_NAMES.contains(&name.to_lowercase().as_str()))
+}
+
+/// File names are either PDFDocEncoded or UTF-16BE with a byte order mark
+fn file_name(object: &Object) -> Option<String> {
+    let bytes = object.as_str().ok()?;
+    match bytes.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => String::from_utf16(&utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<u16>>()).ok(),
+        None => Some(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
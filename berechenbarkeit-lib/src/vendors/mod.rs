@@ -1 +1,93 @@
+use std::fs;
+use std::path::Path;
+
+use once_cell::sync::OnceCell;
+use thiserror::Error;
+
+use crate::vendors::regex::RegexVendor;
+
+pub mod cii;
+pub mod facturx;
 pub mod regex;
+pub mod ubl;
+pub mod xml;
+
+/// Vendor definitions shipped with the binary, see `definitions/metro.toml` for an example of the format.
+static BUNDLED_DEFINITIONS: &[(&str, &str)] = &[
+    ("metro.toml", include_str!("definitions/metro.toml")),
+    ("bauhaus.toml", include_str!("definitions/bauhaus.toml")),
+    ("ikea.toml", include_str!("definitions/ikea.toml")),
+    ("medicalcorner.toml", include_str!("definitions/medicalcorner.toml")),
+    ("moltondiscount.toml", include_str!("definitions/moltondiscount.toml")),
+    ("kokku.toml", include_str!("definitions/kokku.toml")),
+    ("rohalm.toml", include_str!("definitions/rohalm.toml")),
+];
+
+static REGEX_VENDORS: OnceCell<Vec<RegexVendor>> = OnceCell::new();
+
+#[derive(Debug, Error)]
+pub enum VendorDefinitionError {
+    #[error("Failed to read vendor definitions from {0}: {1}")]
+    IoError(String, #[source] std::io::Error),
+    #[error("Invalid vendor definition {0}: {1}")]
+    SyntaxError(String, #[source] basic_toml::Error),
+    #[error("Invalid vendor definition {0}: field '{1}' is not a valid regex: {2}")]
+    InvalidRegexError(String, String, #[source] ::regex::Error),
+    #[error("Invalid vendor definition {0}: field '{1}' is missing the capture group '{2}'")]
+    MissingCaptureGroupError(String, String, String),
+    #[error("Invalid vendor definition {0}: field '{1}' is not a decimal VAT rate: '{2}'")]
+    InvalidVatRateError(String, String, String),
+    #[error("Invalid vendor definition {0}: vendor '{1}' is already defined in another file")]
+    DuplicateVendorError(String, String),
+    #[error("Vendor definitions have already been loaded")]
+    AlreadyInitializedError,
+}
+
+/// Loads the bundled vendor definitions and, if given, every `*.toml` file in `definitions_dir`.
+///
+/// A file in `definitions_dir` replaces the bundled definition of the vendor with the same name.
+pub fn load_regex_vendors(definitions_dir: Option<&Path>) -> Result<Vec<RegexVendor>, VendorDefinitionError> {
+    let mut vendors: Vec<RegexVendor> = BUNDLED_DEFINITIONS
+        .iter()
+        .map(|(source, definition)| RegexVendor::from_definition(source, definition))
+        .collect::<Result<_, _>>()?;
+
+    let Some(definitions_dir) = definitions_dir else {
+        return Ok(vendors);
+    };
+    let read_error = |e| VendorDefinitionError::IoError(definitions_dir.display().to_string(), e);
+    let mut paths: Vec<_> = fs::read_dir(definitions_dir)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()
+        .map_err(read_error)?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "toml"));
+    paths.sort();
+
+    let mut overridden: Vec<String> = vec![];
+    for path in paths {
+        let source = path.display().to_string();
+        let definition = fs::read_to_string(&path).map_err(|e| VendorDefinitionError::IoError(source.clone(), e))?;
+        let vendor = RegexVendor::from_definition(&source, &definition)?;
+        if overridden.contains(&vendor.name) {
+            return Err(VendorDefinitionError::DuplicateVendorError(source, vendor.name));
+        }
+        overridden.push(vendor.name.clone());
+        match vendors.iter().position(|v| v.name == vendor.name) {
+            Some(i) => vendors[i] = vendor,
+            None => vendors.push(vendor),
+        }
+    }
+    Ok(vendors)
+}
+
+/// Loads all vendor definitions, has to be called before the first invoice is parsed.
+pub fn init_regex_vendors(definitions_dir: Option<&Path>) -> Result<(), VendorDefinitionError> {
+    let vendors = load_regex_vendors(definitions_dir)?;
+    REGEX_VENDORS.set(vendors).map_err(|_| VendorDefinitionError::AlreadyInitializedError)
+}
+
+/// All known regex vendors, falling back to the bundled definitions if [`init_regex_vendors`] was never called.
+pub fn regex_vendors() -> &'static [RegexVendor] {
+    REGEX_VENDORS.get_or_init(|| load_regex_vendors(None).expect("bundled vendor definitions are valid"))
+}
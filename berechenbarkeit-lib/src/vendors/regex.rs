@@ -1,94 +1,187 @@
-use crate::{Invoice, InvoiceItem, InvoiceItemType, InvoiceMeta, InvoiceParseError, InvoiceVendor, Vendor};
-use once_cell::sync::Lazy;
+use crate::validation::{ValidationReport, VatSubtotal};
+use crate::vendors::VendorDefinitionError;
+use crate::{Invoice, InvoiceItem, InvoiceItemType, InvoiceMeta, InvoiceParseError, InvoiceVendor, PaymentType, Vendor};
+use bigdecimal::{BigDecimal, One, Zero};
 use regex::{Captures, Regex, RegexBuilder};
+use serde::Deserialize;
 use std::collections::HashMap;
-use time::{Date, PrimitiveDateTime, Time};
+use std::fmt;
+use std::str::FromStr;
+use time::{Date, Month, PrimitiveDateTime, Time};
 
 pub struct RegexVendor {
+    pub name: String,
     invoice_number_regex: Regex,
     invoice_date_regex: Regex,
     invoice_total_regex: Regex,
     invoice_item_regex: Regex,
     invoice_discount_regex: Option<Regex>,
-    vat_classes: HashMap<&'static str, f64>,
-    default_vat_class: Option<f64>,
+    invoice_payment_type_regex: Option<Regex>,
+    invoice_vat_subtotal_regex: Option<Regex>,
+    payment_types: HashMap<String, PaymentType>,
+    vat_classes: HashMap<String, BigDecimal>,
+    default_vat_class: Option<BigDecimal>,
+    vendor_identifier_regex: Option<Regex>,
 }
-#[derive(Default, Debug)]
+
+#[derive(Default, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ItemRegex {
-    re: &'static str,
+    re: String,
+    #[serde(default)]
     multi_line: bool,
     dot_matches_newline: Option<bool>,
 }
+
+/// A [`RegexVendor`] as it is written down in a vendor definition file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegexVendorDefinition {
+    name: String,
+    vendor_identifier: Option<ItemRegex>,
+    invoice_number: ItemRegex,
+    invoice_date: ItemRegex,
+    invoice_total: ItemRegex,
+    invoice_item: ItemRegex,
+    invoice_discount_item: Option<ItemRegex>,
+    /// Where the payment type is printed, if not in a `PAYMENT_TYPE` group of `invoice_total`
+    invoice_payment_type: Option<ItemRegex>,
+    /// One match per VAT rate in the footer of the invoice
+    invoice_vat_subtotal: Option<ItemRegex>,
+    /// Maps the text captured as `PAYMENT_TYPE` (compared case-insensitively) to the payment type
+    #[serde(default)]
+    payment_types: HashMap<String, PaymentType>,
+    /// Rates are written as decimal strings, e.g. `"0.19"`, so they are exact
+    vat_classes: HashMap<String, String>,
+    default_vat_class: Option<String>,
+}
+
 impl RegexVendor {
-    fn new(
-        invoice_number: ItemRegex,
-        invoice_date: ItemRegex,
-        invoice_total: ItemRegex,
-        invoice_item: ItemRegex,
-        invoice_discount_item: Option<ItemRegex>,
-        vat_entries: Vec<(&'static str, f64)>,
-        default_vat_class: Option<f64>,
-    ) -> RegexVendor {
-        let mut vat_map: HashMap<&str, f64> = HashMap::with_capacity(100);
-        for (k, v) in vat_entries {
-            vat_map.insert(k, v);
-        }
-        let build_re = |re: &str, multi_line: bool, dot_matches_new_line: bool| -> Regex {
-            RegexBuilder::new(re).multi_line(multi_line).dot_matches_new_line(dot_matches_new_line).build().unwrap()
+    /// Parses and validates a vendor definition, `source` names the definition in error messages.
+    pub fn from_definition(source: &str, definition: &str) -> Result<RegexVendor, VendorDefinitionError> {
+        let definition: RegexVendorDefinition = basic_toml::from_str(definition).map_err(|e| VendorDefinitionError::SyntaxError(source.to_string(), e))?;
+        let build_re = |field: &str, item: &ItemRegex, required_groups: &[&str]| -> Result<Regex, VendorDefinitionError> {
+            let re = RegexBuilder::new(&item.re)
+                .multi_line(!item.multi_line)
+                .dot_matches_new_line(item.dot_matches_newline.unwrap_or(item.multi_line))
+                .build()
+                .map_err(|e| VendorDefinitionError::InvalidRegexError(source.to_string(), format!("{}.re", field), e))?;
+            for group in required_groups {
+                // Alternatives are separated by '|', one of them has to be present
+                if !group.split('|').any(|g| re.capture_names().flatten().any(|name| name == g)) {
+                    return Err(VendorDefinitionError::MissingCaptureGroupError(
+                        source.to_string(),
+                        format!("{}.re", field),
+                        group.to_string(),
+                    ));
+                }
+            }
+            Ok(re)
         };
-        RegexVendor {
-            invoice_number_regex: build_re(
-                invoice_number.re,
-                !invoice_number.multi_line,
-                invoice_number.dot_matches_newline.unwrap_or(invoice_number.multi_line),
-            ),
-            invoice_total_regex: build_re(
-                invoice_total.re,
-                !invoice_total.multi_line,
-                invoice_total.dot_matches_newline.unwrap_or(invoice_total.multi_line),
-            ),
-            invoice_date_regex: build_re(
-                invoice_date.re,
-                !invoice_date.multi_line,
-                invoice_date.dot_matches_newline.unwrap_or(invoice_date.multi_line),
-            ),
-            invoice_item_regex: build_re(
-                invoice_item.re,
-                !invoice_item.multi_line,
-                invoice_item.dot_matches_newline.unwrap_or(invoice_date.multi_line),
-            ),
-            invoice_discount_regex: invoice_discount_item.map(|item| Regex::new(item.re).unwrap()),
-            vat_classes: vat_map,
-            default_vat_class,
+        let vat_rate = |field: String, rate: &str| -> Result<BigDecimal, VendorDefinitionError> {
+            BigDecimal::from_str(rate.trim()).map_err(|_| VendorDefinitionError::InvalidVatRateError(source.to_string(), field, rate.to_string()))
+        };
+        let vat_classes = definition
+            .vat_classes
+            .iter()
+            .map(|(class, rate)| Ok((class.clone(), vat_rate(format!("vat_classes.{}", class), rate)?)))
+            .collect::<Result<HashMap<String, BigDecimal>, VendorDefinitionError>>()?;
+        let default_vat_class = definition
+            .default_vat_class
+            .as_deref()
+            .map(|rate| vat_rate("default_vat_class".to_string(), rate))
+            .transpose()?;
+        let mut item_groups = vec!["DESC", "AMOUNT", "NET_PRICE_SINGLE|GROSS_PRICE_SINGLE", "NET_PRICE_TOTAL|GROSS_PRICE_TOTAL"];
+        if definition.default_vat_class.is_none() {
+            item_groups.push("VAT");
+        }
+        let invoice_payment_type_regex = definition
+            .invoice_payment_type
+            .map(|item| build_re("invoice_payment_type", &item, &["PAYMENT_TYPE"]))
+            .transpose()?;
+        let mut subtotal_groups = vec!["NET_SUM"];
+        if definition.default_vat_class.is_none() {
+            subtotal_groups.push("VAT");
         }
+        let mut total_groups = vec!["SUM"];
+        if !definition.payment_types.is_empty() && invoice_payment_type_regex.is_none() {
+            total_groups.push("PAYMENT_TYPE");
+        }
+        Ok(RegexVendor {
+            invoice_number_regex: build_re("invoice_number", &definition.invoice_number, &["INVOICE_NUMBER"])?,
+            invoice_date_regex: build_re("invoice_date", &definition.invoice_date, &["year", "month", "day"])?,
+            invoice_total_regex: build_re("invoice_total", &definition.invoice_total, &total_groups)?,
+            invoice_payment_type_regex,
+            invoice_vat_subtotal_regex: definition
+                .invoice_vat_subtotal
+                .map(|item| build_re("invoice_vat_subtotal", &item, &subtotal_groups))
+                .transpose()?,
+            invoice_item_regex: build_re("invoice_item", &definition.invoice_item, &item_groups)?,
+            invoice_discount_regex: definition
+                .invoice_discount_item
+                .map(|item| build_re("invoice_discount_item", &item, &["DESC", "VAT", "NET_PRICE_SINGLE|GROSS_PRICE_TOTAL"]))
+                .transpose()?,
+            vendor_identifier_regex: definition.vendor_identifier.map(|item| build_re("vendor_identifier", &item, &[])).transpose()?,
+            name: definition.name,
+            payment_types: definition
+                .payment_types
+                .into_iter()
+                .map(|(text, payment_type)| (text.to_lowercase(), payment_type))
+                .collect(),
+            vat_classes,
+            default_vat_class,
+        })
     }
 
     pub fn get_meta(&self, invoice_text: &str) -> Result<InvoiceMeta, InvoiceParseError> {
-        let invoice_number: String = self
+        self.get_meta_and_subtotal_errors(invoice_text).map(|(meta, _)| meta)
+    }
+
+    /// Like [`RegexVendor::get_meta`], but also returns the subtotal lines that could not be read and were left out
+    fn get_meta_and_subtotal_errors(&self, invoice_text: &str) -> Result<(InvoiceMeta, Vec<InvoiceParseError>), InvoiceParseError> {
+        let payment_type = self.get_payment_type(invoice_text);
+        let (vat_subtotals, subtotal_errors) = self.get_vat_subtotals(invoice_text);
+        match (self.get_invoice_number(invoice_text), self.get_gross_sum(invoice_text), self.get_date(invoice_text)) {
+            (Ok(invoice_number), Ok(sum_gross), Ok(date)) => Ok((
+                InvoiceMeta {
+                    invoice_number,
+                    sum_gross,
+                    payment_type,
+                    date,
+                    vat_subtotals,
+                },
+                subtotal_errors,
+            )),
+            (invoice_number, sum_gross, date) => Err(InvoiceParseError::from_list(
+                [invoice_number.err(), sum_gross.err(), date.err()].into_iter().flatten().collect(),
+            )),
+        }
+    }
+
+    fn get_invoice_number(&self, invoice_text: &str) -> Result<String, InvoiceParseError> {
+        Ok(self
             .invoice_number_regex
             .captures(invoice_text)
             .and_then(|captures| captures.name("INVOICE_NUMBER"))
             .ok_or(InvoiceParseError::FieldMissingError("INVOICE_NUMBER".to_string()))?
             .as_str()
             .trim()
-            .to_string();
-        let payment_type = None;
-        Ok(InvoiceMeta {
-            invoice_number,
-            sum_gross: self.get_gross_sum(&invoice_text)?,
-            payment_type,
-            date: self.get_date(&invoice_text)?,
-        })
+            .to_string())
+    }
+
+    /// Payment type of the invoice, `None` if it isn't printed or its wording is not in the vendor's mapping
+    fn get_payment_type(&self, invoice_text: &str) -> Option<PaymentType> {
+        let captures = self.invoice_payment_type_regex.as_ref().unwrap_or(&self.invoice_total_regex).captures(invoice_text)?;
+        self.payment_types.get(&captures.name("PAYMENT_TYPE")?.as_str().trim().to_lowercase()).copied()
     }
 
-    fn get_gross_sum(&self, invoice_text: &str) -> Result<f64, InvoiceParseError> {
-        return Ok(parse_as_float(
-            self.invoice_total_regex
-                .captures(invoice_text)
-                .and_then(|c| c.name("SUM"))
-                .ok_or(InvoiceParseError::FieldMissingError("SUM".to_string()))?
-                .as_str(),
-        ));
+    fn get_gross_sum(&self, invoice_text: &str) -> Result<BigDecimal, InvoiceParseError> {
+        let sum = self
+            .invoice_total_regex
+            .captures(invoice_text)
+            .and_then(|c| c.name("SUM"))
+            .ok_or(InvoiceParseError::FieldMissingError("SUM".to_string()))?;
+        parse_as_decimal("SUM", sum.as_str(), line_of(invoice_text, sum.start()))
     }
 
     fn get_date(&self, invoice_text: &str) -> Result<PrimitiveDateTime, InvoiceParseError> {
@@ -96,103 +189,128 @@ impl RegexVendor {
             .invoice_date_regex
             .captures(invoice_text)
             .ok_or(InvoiceParseError::FieldMissingError("INVOICE_DATE".to_string()))?;
-        let (y, m, d): (i32, u8, u8) = (
-            date_matches
-                .name("year")
-                .ok_or(InvoiceParseError::FieldMissingError("date.year".to_string()))?
-                .as_str()
-                .parse()?,
-            date_matches
-                .name("month")
-                .ok_or(InvoiceParseError::FieldMissingError("date.month".to_string()))?
-                .as_str()
-                .parse()?,
-            date_matches
-                .name("day")
-                .ok_or(InvoiceParseError::FieldMissingError("date.day".to_string()))?
-                .as_str()
-                .parse()?,
-        );
-        let (h, i, s): (u8, u8, u8) = (
-            match date_matches.name("hour") {
-                Some(re_match) => re_match.as_str().parse()?,
-                None => 0,
-            },
-            match date_matches.name("min") {
-                Some(re_match) => re_match.as_str().parse()?,
-                None => 0,
-            },
-            match date_matches.name("sec") {
-                Some(re_match) => re_match.as_str().parse()?,
-                None => 0,
-            },
-        );
-        Ok(PrimitiveDateTime::new(Date::from_calendar_date(y, m.try_into()?, d)?, Time::from_hms(h, i, s)?))
+        let whole_match = date_matches.get(0).map_or("", |m| m.as_str());
+        let line = date_matches.get(0).map_or(1, |m| line_of(invoice_text, m.start()));
+        let required =
+            |name: &str| -> Result<&str, InvoiceParseError> { Ok(date_matches.name(name).ok_or(InvoiceParseError::FieldMissingError(format!("date.{}", name)))?.as_str()) };
+        let optional = |name: &str| date_matches.name(name).map_or("0", |m| m.as_str());
+        let invalid_date = |reason: String| InvoiceParseError::InvalidFieldError("INVOICE_DATE".to_string(), whole_match.to_string(), line, reason);
+
+        let y: i32 = parse_field("date.year", required("year")?, line)?;
+        let m: u8 = parse_field("date.month", required("month")?, line)?;
+        let d: u8 = parse_field("date.day", required("day")?, line)?;
+        let h: u8 = parse_field("date.hour", optional("hour"), line)?;
+        let i: u8 = parse_field("date.min", optional("min"), line)?;
+        let s: u8 = parse_field("date.sec", optional("sec"), line)?;
+        let month = Month::try_from(m).map_err(|e| invalid_date(e.to_string()))?;
+        let date = Date::from_calendar_date(y, month, d).map_err(|e| invalid_date(e.to_string()))?;
+        let time = Time::from_hms(h, i, s).map_err(|e| invalid_date(e.to_string()))?;
+        Ok(PrimitiveDateTime::new(date, time))
     }
 
+    /// The readable subtotals and the problems of the unreadable ones, which only weaken the validation of the invoice
+    fn get_vat_subtotals(&self, invoice_text: &str) -> (Vec<VatSubtotal>, Vec<InvoiceParseError>) {
+        let Some(re) = &self.invoice_vat_subtotal_regex else {
+            return (vec![], vec![]);
+        };
+        let mut subtotals: Vec<VatSubtotal> = vec![];
+        let mut errors: Vec<InvoiceParseError> = vec![];
+        for captures in re.captures_iter(invoice_text) {
+            let line = captures.get(0).map_or(1, |m| line_of(invoice_text, m.start()));
+            let subtotal = self.get_vat_rate(&captures, line).and_then(|vat| {
+                Ok(VatSubtotal {
+                    vat,
+                    net: parse_as_decimal("NET_SUM", required_group(&captures, "NET_SUM", line)?, line)?,
+                    vat_amount: captures.name("VAT_SUM").map(|m| parse_as_decimal("VAT_SUM", m.as_str(), line)).transpose()?,
+                })
+            });
+            match subtotal {
+                Ok(subtotal) => subtotals.push(subtotal),
+                Err(e) => errors.push(e),
+            }
+        }
+        (subtotals, errors.into_iter().flat_map(InvoiceParseError::into_list).collect())
+    }
+
+    /// Parses all items of the invoice, reporting the problems of every broken item instead of only the first one
     pub fn get_items(&self, invoice_text: &str) -> Result<Vec<InvoiceItem>, InvoiceParseError> {
         let mut position_counter: u32 = 1;
-        let discount_items: Vec<InvoiceItem> = match &self.invoice_discount_regex {
-            Some(re) => invoice_text
-                .lines()
-                .filter(|line| re.is_match(line))
-                .map(|line| self.extract_discount_item_from_capture_groups(re.captures(line).unwrap()))
-                .collect::<Result<Vec<InvoiceItem>, InvoiceParseError>>()?,
-            None => vec![],
-        };
-        let mut items: Vec<InvoiceItem> = self
-            .invoice_item_regex
-            .captures_iter(&invoice_text)
-            .map(|captures| self.extract_item_from_capture_groups(captures, &mut position_counter))
-            .collect::<Result<Vec<InvoiceItem>, InvoiceParseError>>()?;
-        items.extend(discount_items);
-        Ok(items)
+        let mut items: Vec<InvoiceItem> = vec![];
+        let mut errors: Vec<InvoiceParseError> = vec![];
+        for captures in self.invoice_item_regex.captures_iter(invoice_text) {
+            let line = captures.get(0).map_or(1, |m| line_of(invoice_text, m.start()));
+            match self.extract_item_from_capture_groups(captures, line, &mut position_counter) {
+                Ok(item) => items.push(item),
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Some(re) = &self.invoice_discount_regex {
+            for (i, captures) in invoice_text.lines().enumerate().filter_map(|(i, line)| Some((i, re.captures(line)?))) {
+                match self.extract_discount_item_from_capture_groups(captures, i + 1) {
+                    Ok(item) => items.push(item),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(items),
+            false => Err(InvoiceParseError::from_list(errors)),
+        }
     }
 
-    fn extract_discount_item_from_capture_groups(&self, groups: Captures) -> Result<InvoiceItem, InvoiceParseError> {
-        let vat: f64 = self.get_vat_rate_from_class(groups.name("VAT").unwrap().as_str().trim())?;
-        let discount: f64 = match groups.name("NET_PRICE_SINGLE") {
-            Some(net_price_total) => parse_as_float(net_price_total.as_str().trim()),
-            None => parse_as_float(groups.name("GROSS_PRICE_TOTAL").unwrap().as_str().trim()) * (1f64 - (vat / (1f64 + vat))),
+    fn extract_discount_item_from_capture_groups(&self, groups: Captures, line: usize) -> Result<InvoiceItem, InvoiceParseError> {
+        let vat: BigDecimal = self.get_vat_rate(&groups, line)?;
+        let discount: BigDecimal = match groups.name("NET_PRICE_SINGLE") {
+            Some(net_price_total) => parse_as_decimal("NET_PRICE_SINGLE", net_price_total.as_str(), line)?,
+            None => net_from_gross(&parse_as_decimal("GROSS_PRICE_TOTAL", required_group(&groups, "GROSS_PRICE_TOTAL", line)?, line)?, &vat),
         };
         Ok(InvoiceItem {
             typ: InvoiceItemType::Credit,
             pos: u32::MAX,
             article_number: "".to_string(),
-            description: self.extract_description(groups)?,
-            net_price_single: discount,
+            ean: None,
+            description: required_group(&groups, "DESC", line)?.to_string(),
+            net_price_single: discount.clone(),
             vat,
-            amount: 1f64,
+            amount: BigDecimal::one(),
             net_total_price: discount,
+            packaging_unit_amount: None,
         })
     }
 
-    fn extract_item_from_capture_groups(&self, groups: Captures, pos_counter: &mut u32) -> Result<InvoiceItem, InvoiceParseError> {
+    fn extract_item_from_capture_groups(&self, groups: Captures, line: usize, pos_counter: &mut u32) -> Result<InvoiceItem, InvoiceParseError> {
         let pos: u32 = match groups.name("POS") {
-            Some(p) => p.as_str().parse::<u32>()?,
-            None => pos_counter.clone(),
-        };
-        *pos_counter = *pos_counter + 1u32;
-        let vat: f64 = match groups.name("VAT") {
-            Some(class) => self.get_vat_rate_from_class(class.as_str().trim())?,
-            None => self.default_vat_class.ok_or(InvoiceParseError::FieldMissingError("VAT".to_string()))?,
-        };
-        let packaging_unit_amount: f64 = match groups.name("PU_AMOUNT") {
-            Some(pu_amount) => parse_as_float(pu_amount.as_str().trim()),
-            None => 1f64,
+            Some(p) => parse_field("POS", p.as_str(), line)?,
+            None => *pos_counter,
         };
-        let amount: f64 = parse_as_float(groups.name("AMOUNT").unwrap().as_str().trim());
-        let net_price_single: f64 = match groups.name("NET_PRICE_SINGLE") {
-            Some(net_price_single) => parse_as_float(net_price_single.as_str().trim()),
-            None => (parse_as_float(groups.name("GROSS_PRICE_SINGLE").unwrap().as_str().trim()) * (1f64 - (vat / (1f64 + vat))) * 1000f64).round() / 1000f64,
+        *pos_counter += 1u32;
+        let vat: BigDecimal = self.get_vat_rate(&groups, line)?;
+        let packaging_unit_amount: Option<BigDecimal> = groups
+            .name("PU_AMOUNT")
+            .map(|pu_amount| parse_as_decimal("PU_AMOUNT", pu_amount.as_str(), line))
+            .transpose()?;
+        if packaging_unit_amount.as_ref().is_some_and(BigDecimal::is_zero) {
+            let matched = groups.name("PU_AMOUNT").map_or("", |m| m.as_str().trim()).to_string();
+            return Err(InvoiceParseError::InvalidFieldError(
+                "PU_AMOUNT".to_string(),
+                matched,
+                line,
+                "packaging unit of zero".to_string(),
+            ));
+        }
+        let pieces_per_unit: BigDecimal = packaging_unit_amount.clone().unwrap_or_else(BigDecimal::one);
+        let amount: BigDecimal = parse_as_decimal("AMOUNT", required_group(&groups, "AMOUNT", line)?, line)?;
+        let net_price_single: BigDecimal = match groups.name("NET_PRICE_SINGLE") {
+            Some(net_price_single) => parse_as_decimal("NET_PRICE_SINGLE", net_price_single.as_str(), line)?,
+            None => net_from_gross(&parse_as_decimal("GROSS_PRICE_SINGLE", required_group(&groups, "GROSS_PRICE_SINGLE", line)?, line)?, &vat),
         };
-        let net_price_total: f64 = match groups.name("NET_PRICE_TOTAL") {
-            Some(net_price_total) => parse_as_float(net_price_total.as_str().trim()),
-            None => (parse_as_float(groups.name("GROSS_PRICE_TOTAL").unwrap().as_str().trim()) * (1f64 - (vat / (1f64 + vat))) * 1000f64).round() / 1000f64,
+        let net_price_total: BigDecimal = match groups.name("NET_PRICE_TOTAL") {
+            Some(net_price_total) => parse_as_decimal("NET_PRICE_TOTAL", net_price_total.as_str(), line)?,
+            None => net_from_gross(&parse_as_decimal("GROSS_PRICE_TOTAL", required_group(&groups, "GROSS_PRICE_TOTAL", line)?, line)?, &vat),
         };
 
         Ok(InvoiceItem {
-            typ: if (net_price_single * amount) >= 0.0f64 {
+            typ: if (&net_price_single * &amount) >= BigDecimal::zero() {
                 InvoiceItemType::Expense
             } else {
                 InvoiceItemType::Credit
@@ -202,247 +320,103 @@ impl RegexVendor {
                 Some(m) => m.as_str().to_string(),
                 None => "".to_string(),
             },
-            description: self.extract_description(groups)?,
-            net_price_single: ((net_price_single / packaging_unit_amount) * 1000f64).round() / 1000f64,
+            ean: groups.name("EAN").map(|m| m.as_str().trim().to_string()).filter(|ean| !ean.is_empty()),
+            description: required_group(&groups, "DESC", line)?.to_string(),
+            net_price_single: (net_price_single / &pieces_per_unit).round(NET_PRICE_SCALE),
             vat,
-            amount: amount * packaging_unit_amount,
+            amount: amount * pieces_per_unit,
             net_total_price: net_price_total,
+            packaging_unit_amount,
         })
     }
 
-    fn extract_description(&self, groups: Captures) -> Result<String, InvoiceParseError> {
-        Ok(groups.name("DESC").ok_or(InvoiceParseError::FieldMissingError("DESC".to_string()))?.as_str().to_string())
+    /// VAT rate of an item, from its VAT class or, if the item has none, the vendor's default
+    fn get_vat_rate(&self, groups: &Captures, line: usize) -> Result<BigDecimal, InvoiceParseError> {
+        match groups.name("VAT") {
+            Some(class) => {
+                let class = class.as_str().trim();
+                self.vat_classes
+                    .get(class)
+                    .ok_or_else(|| InvoiceParseError::UnrecognizedVatClass(class.to_string(), line))
+                    .cloned()
+            }
+            None => self.default_vat_class.clone().ok_or(InvoiceParseError::ItemFieldMissingError("VAT".to_string(), line)),
+        }
     }
+}
 
-    fn get_vat_rate_from_class(&self, class: &str) -> Result<f64, InvoiceParseError> {
-        self.vat_classes
-            .get(class)
-            .ok_or_else(|| InvoiceParseError::UnrecognizedVatClass(class.to_string()))
-            .copied()
-    }
+/// 1-based line number of a byte offset in the invoice text
+fn line_of(invoice_text: &str, offset: usize) -> usize {
+    invoice_text[..offset].matches('\n').count() + 1
 }
 
-fn parse_as_float(float: &str) -> f64 {
+fn required_group<'t>(groups: &Captures<'t>, name: &str, line: usize) -> Result<&'t str, InvoiceParseError> {
+    Ok(groups.name(name).ok_or(InvoiceParseError::ItemFieldMissingError(name.to_string(), line))?.as_str())
+}
+
+fn parse_field<T>(field: &str, value: &str, line: usize) -> Result<T, InvoiceParseError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e: T::Err| InvoiceParseError::InvalidFieldError(field.to_string(), value.trim().to_string(), line, e.to_string()))
+}
+
+fn parse_as_decimal(field: &str, decimal: &str, line: usize) -> Result<BigDecimal, InvoiceParseError> {
     // remove 1000-dot and all whitespace
-    let raw = float.replace(".", "").replacen(" ", "", 1000);
-    let is_negative = raw.contains("-");
-    let absolute = raw.replace("-", "").replace(",", ".");
+    let raw = decimal.trim().replace('.', "").replacen(' ', "", 1000);
+    let is_negative = raw.contains('-');
+    let absolute = BigDecimal::from_str(&raw.replace('-', "").replace(',', "."))
+        .map_err(|e| InvoiceParseError::InvalidFieldError(field.to_string(), decimal.trim().to_string(), line, e.to_string()))?;
     if is_negative {
-        return -1f64 * (absolute.parse::<f64>().unwrap());
+        return Ok(-absolute);
     }
-    return absolute.parse::<f64>().unwrap();
+    Ok(absolute)
+}
+
+/// Net prices calculated from gross prices can't be exact, so they are rounded to this many decimal places
+const NET_PRICE_SCALE: i64 = 4;
+
+fn net_from_gross(gross: &BigDecimal, vat: &BigDecimal) -> BigDecimal {
+    (gross / (BigDecimal::one() + vat)).round(NET_PRICE_SCALE)
 }
 
 impl Vendor for RegexVendor {
     fn extract_invoice_data(&self, pdf: &[u8], vendor: InvoiceVendor) -> anyhow::Result<Invoice> {
         let text = pdf_extract::extract_text_from_mem(pdf)?;
-        Ok(Invoice {
-            vendor: vendor,
-            meta: self.get_meta(&text)?,
-            items: self.get_items(&text)?,
-        })
+        Ok(self.extract_invoice_data_from_text(&text, vendor)?)
     }
-}
-
-pub static METRO: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"RECHNUNGS?-? ?NR\.?\:?\s+(?P<INVOICE_NUMBER>[\.\d\/]+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"RECHNUNGSDATUM:\s+(?P<day>\d\d)\.(?P<month>\d\d)\.(?P<year>\d{4}) (?P<hour>\d\d):(?P<min>\d\d)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"SUMME EUR\s+(?P<SUM>[\d\.,\-]+)([\s\-]+(?P<PAYMENT_TYPE>[a-zA-Z0-9:\-\., ]+) +[\d\.,\-]+)?",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"^(?P<MM>.) (?P<ARTNR>\d{6}\.\d) (?P<EAN>[\d ]{14}) (?P<DESC>.{31}) (?P<PACK>.{2}) (?P<EINZELPREIS>.{11}) (?P<PU_AMOUNT>.{10}) (?P<NET_PRICE_SINGLE>.{10}) (?P<AMOUNT>.{6}) (?P<NET_PRICE_TOTAL>.{11}) (?P<VAT>.) (?P<STUECKPREIS>.{10})[  ](?P<INT>.) (?P<KD>.+)?$", // editorconfig-checker-disable-lin
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        Some(ItemRegex {
-            re: r"^ {26}(?P<DESC>.{50}) *(?P<NET_PRICE_SINGLE>.{11}) (?P<VAT>.)?[ 0-9]{12}$",
-            multi_line: false,
-            ..ItemRegex::default()
-        }),
-        vec![("A", 0.19f64), ("B", 0.07f64)],
-        None,
-    )
-});
-
-pub static BAUHAUS: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"Einzelrechnung\s+Nr\.\s+(?P<INVOICE_NUMBER>[\.\d\/]+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Rechnungsdatum\s+(?P<day>\d\d)\.(?P<month>\d\d)\.(?P<year>\d{4})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Zu zahlender Betrag\s+(?P<SUM>[\d\.,\-]+)\ EUR",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"^(?P<POS>\d+)\s+(?P<ARTNR>\d{8})\s+(?P<DESC>.{1,100})\s+(?P<AMOUNT>\d{1,6}) (ST|KAR)\s+(?P<GROSS_PRICE_SINGLE>.{1,7})\s+(?P<GROSS_PRICE_TOTAL>.{1,7})\s+(?P<VAT>\w)$", // editorconfig-checker-disable-lin
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("C", 0.19f64)],
-        None,
-    )
-});
-
-pub static IKEA: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"Rechnungsnummer: (?P<INVOICE_NUMBER>\w+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Rechnungsdatum:\s+(?P<day>\d{1,2})\.(?P<month>\d{1,2})\.(?P<year>\d{2,4})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Rechnungssumme:\s+€\s+(?P<SUM>[0-9,\.]+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"^(?P<ARTNR>\d{2,3}\.\d{2,3}\.\d{2,3})\s+(?P<DESC>.+)\s+(?P<AMOUNT>\d+)\s+(?P<GROSS_PRICE_SINGLE>\d{1,5},\d{0,2})\s+(?P<VAT>\d{1,2}) %\s+€ (?P<GROSS_PRICE_TOTAL>[0-9,\.]+)$", // editorconfig-checker-disable-lin
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("19", 0.19f64), ("7", 0.07f64)],
-        None,
-    )
-});
-
-pub static MEDICALCORNER: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"Rechnung\s+(?P<INVOICE_NUMBER>\w+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Rechnungsdatum:\s+\nKundennummer:\s+\nLieferschein:\s+\nLieferdatum:\s+\nBearbeiter:\s+\n.+\n(?<day>\d{1,2})\.(?<month>\d{2})\.(?<year>\d{4})",
-            multi_line: true,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Gesamt (?<SUM>\d+,\d{2}) EUR",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"\n\n(?P<POS>\d+) (?P<ARTNR>[A-Z0-9-_]+([\w&&[^A-Z]]{4})?) (?P<DESC>.+?) (?P<AMOUNT>\d+) (?P<VAT>\d+)% (?P<GROSS_PRICE_SINGLE>\d+,\d{2}) (?P<GROSS_PRICE_TOTAL>\d+,\d{2})",
-            multi_line: true,
-            dot_matches_newline: Some(true),
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("0", 0.0f64), ("19", 0.19f64), ("7", 0.07f64)],
-        None,
-    )
-});
-
-pub static MOLTONDISCOUNT: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"Rechnungs-Nr\.\s+(?P<INVOICE_NUMBER>[\w-]+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Datum\s+(?P<day>\d{1,2})\.(?P<month>\d{2})\.(?P<year>\d{4})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Gesamtsumme:\s+(?P<SUM>\d+,\d{2})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"\n(?P<POS>\d+)\s+(?P<DESC>.+?)\s+(?P<AMOUNT>\d+)\s+(?P<GROSS_PRICE_SINGLE>\d+,\d{2})\s+¬\s+(?P<GROSS_PRICE_TOTAL>\d+,\d{2})",
-            multi_line: true,
-            dot_matches_newline: Some(true),
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("19", 0.19f64)],
-        Some(0.19f64),
-    )
-});
 
-pub static KOKKU: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"Rechnungsnummer\s+(?P<INVOICE_NUMBER>\w+)",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Rechnungsdatum\s+(?P<day>\d{2})\.(?P<month>\d{2})\.(?P<year>\d{4})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Gesamtsumme\s+inkl\.\s+MwSt\.:\s+(?P<SUM>\d{1,},\d{2})\s+€",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"\n\n(?P<DESC>[\w\d -]+?)\s+(?P<VAT>\d{1,2}\.\d{1,2})%(?P<AMOUNT>\d+)\s+(?P<GROSS_PRICE_SINGLE>\d{1,},\d{1,2})\s+€\s+(?P<GROSS_PRICE_TOTAL>\d{1,},\d{1,2})\s+€",
-            multi_line: true,
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("7.0", 0.07f64)],
-        None,
-    )
-});
+    fn extract_invoice_data_from_text(&self, invoice_text: &str, vendor: InvoiceVendor) -> Result<Invoice, InvoiceParseError> {
+        match (self.get_meta_and_subtotal_errors(invoice_text), self.get_items(invoice_text)) {
+            (Ok((meta, subtotal_errors)), Ok(items)) => Ok(Invoice {
+                vendor,
+                validation: ValidationReport {
+                    problems: subtotal_errors.iter().map(|e| format!("Zwischensumme übersprungen: {}", e)).collect(),
+                    ..ValidationReport::new(&meta, &items)
+                },
+                meta,
+                items,
+            }),
+            (meta, items) => Err(InvoiceParseError::from_list([meta.err(), items.err()].into_iter().flatten().collect())),
+        }
+    }
 
-pub static ROHALM: Lazy<RegexVendor> = Lazy::new(|| {
-    RegexVendor::new(
-        ItemRegex {
-            re: r"(?P<INVOICE_NUMBER>[\w\d]+)\s+(?P<CUSTOMER_NUMBER>\d+)\s+\d{2}\.\d{2}\.\d{4}\s+\d{2}\.\d{2}\.\d{4}",
-            multi_line: true,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"(?P<INVOICE_NUMBER>[\w\w]+)\s+(?P<CUSTOMER_NUMBER>\d+)\s+(?P<day>\d{2})\.(?P<month>\d{2})\.(?P<year>\d{4})\s+\d{2}\.\d{2}\.\d{4}",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"Gesamtbetrag\*\s+(?P<SUM>\d{1,},\d{1,2})",
-            multi_line: false,
-            ..ItemRegex::default()
-        },
-        ItemRegex {
-            re: r"\n\n(?P<POS>\d+)\s+(?P<DESC>.+?)\s+(?P<AMOUNT>\d+)\s+(?P<GROSS_PRICE_SINGLE>\d{1,},\d{2})\s+(?P<GROSS_PRICE_TOTAL>\d{1,},\d{2})",
-            multi_line: true,
-            dot_matches_newline: Some(true),
-            ..ItemRegex::default()
-        },
-        None,
-        vec![("19.0", 0.19f64)],
-        Some(0.19f64),
-    )
-});
+    fn match_score(&self, invoice_text: &str) -> u32 {
+        // Without a parseable invoice header this vendor is of no use, no matter how similar the text looks
+        if self.get_meta(invoice_text).is_err() {
+            return 0;
+        }
+        let mut score = 1;
+        if self.invoice_item_regex.is_match(invoice_text) {
+            score += 1;
+        }
+        if self.vendor_identifier_regex.as_ref().is_some_and(|re| re.is_match(invoice_text)) {
+            score += 2;
+        }
+        score
+    }
+}
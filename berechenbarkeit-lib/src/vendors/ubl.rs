@@ -0,0 +1,167 @@
+//! OASIS Universal Business Language (UBL) 2.1, the second XRechnung syntax
+
+use bigdecimal::{BigDecimal, One, Zero};
+use roxmltree::{Document, Node};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use super::xml::{children, document, find, payment_type_from_code, Xml};
+use crate::validation::{ValidationReport, VatSubtotal};
+use crate::{Invoice, InvoiceItem, InvoiceItemType, InvoiceMeta, InvoiceParseError, InvoiceVendor};
+
+pub fn parse_ubl(xml: &str) -> Result<Invoice, InvoiceParseError> {
+    from_document(&document(xml)?)
+}
+
+pub(crate) fn from_document(doc: &Document) -> Result<Invoice, InvoiceParseError> {
+    let root = doc.root_element();
+    // Invoices and credit notes only differ in the names of their lines and quantities
+    let (line_name, quantity_path) = match root.tag_name().name() {
+        "Invoice" => ("InvoiceLine", "InvoicedQuantity"),
+        "CreditNote" => ("CreditNoteLine", "CreditedQuantity"),
+        other => return Err(InvoiceParseError::InvalidXmlError(format!("unexpected root element {}", other))),
+    };
+    let xml = Xml { doc };
+    // Credit notes list positive amounts, we store them as negative ones
+    let sign = match root.tag_name().name() == "CreditNote" || xml.optional_text(root, "InvoiceTypeCode") == Some("381") {
+        true => -BigDecimal::one(),
+        false => BigDecimal::one(),
+    };
+    let ubl = Ubl { xml, sign, quantity_path };
+
+    let mut errors: Vec<InvoiceParseError> = vec![];
+    let mut items: Vec<InvoiceItem> = vec![];
+    for (i, line) in children(root, line_name).enumerate() {
+        match ubl.line_item(line, i as u32 + 1) {
+            Ok(item) => items.push(item),
+            Err(e) => errors.push(e),
+        }
+    }
+    for allowance_charge in children(root, "AllowanceCharge") {
+        match ubl.allowance_charge(allowance_charge) {
+            Ok(item) => items.push(item),
+            Err(e) => errors.push(e),
+        }
+    }
+    let meta = ubl.meta(root);
+    let party = ubl.xml.required(root, "AccountingSupplierParty/Party");
+    let seller = party.and_then(|party| match ubl.xml.optional_text(party, "PartyLegalEntity/RegistrationName") {
+        Some(name) => Ok(name),
+        None => ubl.xml.text(party, "PartyName/Name"),
+    });
+
+    match (meta, seller, errors.is_empty()) {
+        (Ok(meta), Ok(seller), true) => Ok(Invoice {
+            vendor: InvoiceVendor::from_seller_name(seller),
+            validation: ValidationReport::new(&meta, &items),
+            meta,
+            items,
+        }),
+        (meta, seller, _) => {
+            errors.extend([meta.err(), seller.err()].into_iter().flatten());
+            Err(InvoiceParseError::from_list(errors))
+        }
+    }
+}
+
+struct Ubl<'a, 'input> {
+    xml: Xml<'a, 'input>,
+    sign: BigDecimal,
+    quantity_path: &'static str,
+}
+
+impl<'a, 'input> Ubl<'a, 'input> {
+    /// VAT rate of a UBL tax category, categories like exempt (E) or zero rated (Z) have no percentage
+    fn vat_rate(&self, category: Node<'a, 'input>) -> Result<BigDecimal, InvoiceParseError> {
+        Ok(self.xml.optional_decimal(category, "Percent")?.unwrap_or_else(BigDecimal::zero) / BigDecimal::from(100))
+    }
+
+    fn date(&self, node: Node<'a, 'input>, path: &str) -> Result<PrimitiveDateTime, InvoiceParseError> {
+        let element = self.xml.required(node, path)?;
+        let text = element.text().unwrap_or("").trim();
+        let invalid_date = |reason: String| InvoiceParseError::InvalidFieldError(path.to_string(), text.to_string(), self.xml.line(element), reason);
+        // UBL dates are always YYYY-MM-DD
+        let parts = text.splitn(3, '-').map(|part| part.parse::<u16>().ok()).collect::<Option<Vec<u16>>>();
+        let Some([y, m, d]) = parts.as_deref() else {
+            return Err(invalid_date("expected a date like 2024-05-24".to_string()));
+        };
+        let month = Month::try_from(*m as u8).map_err(|e| invalid_date(e.to_string()))?;
+        let date = Date::from_calendar_date((*y).into(), month, *d as u8).map_err(|e| invalid_date(e.to_string()))?;
+        Ok(PrimitiveDateTime::new(date, Time::MIDNIGHT))
+    }
+
+    fn meta(&self, root: Node<'a, 'input>) -> Result<InvoiceMeta, InvoiceParseError> {
+        // Only the tax total in the document currency has subtotals, a second one may state the VAT in the accounting currency
+        let vat_subtotals = children(root, "TaxTotal")
+            .flat_map(|total| children(total, "TaxSubtotal"))
+            .map(|subtotal| {
+                Ok(VatSubtotal {
+                    vat: self.vat_rate(self.xml.required(subtotal, "TaxCategory")?)?,
+                    net: self.xml.decimal(subtotal, "TaxableAmount")? * &self.sign,
+                    vat_amount: self.xml.optional_decimal(subtotal, "TaxAmount")?.map(|amount| amount * &self.sign),
+                })
+            })
+            .collect::<Result<Vec<VatSubtotal>, InvoiceParseError>>()?;
+        Ok(InvoiceMeta {
+            invoice_number: self.xml.text(root, "ID")?.to_string(),
+            sum_gross: self.xml.decimal(root, "LegalMonetaryTotal/TaxInclusiveAmount")? * &self.sign,
+            payment_type: children(root, "PaymentMeans").find_map(|means| self.xml.optional_text(means, "PaymentMeansCode").and_then(payment_type_from_code)),
+            date: self.date(root, "IssueDate")?,
+            vat_subtotals,
+        })
+    }
+
+    fn line_item(&self, line: Node<'a, 'input>, counter: u32) -> Result<InvoiceItem, InvoiceParseError> {
+        let item = self.xml.required(line, "Item")?;
+        let net_price_single = self.xml.unit_price(line, "Price/PriceAmount", "Price/BaseQuantity")?;
+        let amount = self.xml.decimal(line, self.quantity_path)? * &self.sign;
+
+        Ok(InvoiceItem {
+            typ: match (&net_price_single * &amount) >= BigDecimal::zero() {
+                true => InvoiceItemType::Expense,
+                false => InvoiceItemType::Credit,
+            },
+            // Line ids don't have to be numeric
+            pos: self.xml.optional_text(line, "ID").and_then(|id| id.parse().ok()).unwrap_or(counter),
+            article_number: self.xml.optional_text(item, "SellersItemIdentification/ID").unwrap_or("").to_string(),
+            ean: find(item, "StandardItemIdentification/ID")
+                .filter(|id| id.attribute("schemeID") == Some("0160"))
+                .and_then(|id| id.text())
+                .map(|ean| ean.trim().to_string()),
+            description: self.xml.text(item, "Name")?.to_string(),
+            net_price_single,
+            vat: self.vat_rate(self.xml.required(item, "ClassifiedTaxCategory")?)?,
+            amount,
+            net_total_price: self.xml.decimal(line, "LineExtensionAmount")? * &self.sign,
+            packaging_unit_amount: None,
+        })
+    }
+
+    /// Document level allowance (e.g. a discount) or charge (e.g. shipping), stored as an item of its own
+    fn allowance_charge(&self, allowance_charge: Node<'a, 'input>) -> Result<InvoiceItem, InvoiceParseError> {
+        let is_charge = self.xml.text(allowance_charge, "ChargeIndicator")? == "true";
+        let amount = self.xml.decimal(allowance_charge, "Amount")? * &self.sign;
+        let net = match is_charge {
+            true => amount,
+            false => -amount,
+        };
+        Ok(InvoiceItem {
+            typ: match net >= BigDecimal::zero() {
+                true => InvoiceItemType::Expense,
+                false => InvoiceItemType::Credit,
+            },
+            pos: u32::MAX,
+            article_number: "".to_string(),
+            ean: None,
+            description: match self.xml.optional_text(allowance_charge, "AllowanceChargeReason") {
+                Some(reason) => reason.to_string(),
+                None if is_charge => "Zuschlag".to_string(),
+                None => "Nachlass".to_string(),
+            },
+            net_price_single: net.clone(),
+            vat: self.vat_rate(self.xml.required(allowance_charge, "TaxCategory")?)?,
+            amount: BigDecimal::one(),
+            net_total_price: net,
+            packaging_unit_amount: None,
+        })
+    }
+}
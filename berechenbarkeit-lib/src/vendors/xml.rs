@@ -0,0 +1,107 @@
+//! Structured XML invoices (XRechnung, ZUGFeRD / Factur-X) and the helpers shared by their syntaxes
+
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, One, Zero};
+use roxmltree::{Document, Node};
+
+use super::{cii, ubl};
+use crate::{Invoice, InvoiceParseError, PaymentType};
+
+/// Net prices per unit that have to be calculated are rounded to this many decimal places
+const NET_PRICE_SCALE: i64 = 4;
+
+/// Parses an invoice in either of the XRechnung syntaxes, UN/CEFACT CII or UBL 2.1
+pub fn parse_xml(xml: &str) -> Result<Invoice, InvoiceParseError> {
+    let doc = document(xml)?;
+    match doc.root_element().tag_name().name() {
+        "CrossIndustryInvoice" => cii::from_document(&doc),
+        "Invoice" | "CreditNote" => ubl::from_document(&doc),
+        root => Err(InvoiceParseError::InvalidXmlError(format!("unexpected root element {}", root))),
+    }
+}
+
+/// Whether an uploaded file looks like XML rather than a PDF
+pub fn is_xml(file: &[u8]) -> bool {
+    let file = file.strip_prefix("\u{feff}".as_bytes()).unwrap_or(file);
+    file.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+pub(crate) fn document(xml: &str) -> Result<Document<'_>, InvoiceParseError> {
+    Document::parse(xml.trim_start_matches('\u{feff}')).map_err(|e| InvoiceParseError::InvalidXmlError(e.to_string()))
+}
+
+/// Payment means code (UNTDID 4461) as used by CII and UBL
+pub(crate) fn payment_type_from_code(code: &str) -> Option<PaymentType> {
+    match code.trim() {
+        "10" => Some(PaymentType::Cash),
+        "30" | "31" | "42" | "49" | "58" | "59" => Some(PaymentType::BankTransfer),
+        "48" | "55" => Some(PaymentType::DebitCard),
+        "54" => Some(PaymentType::CreditCard),
+        "1" | "97" | "ZZZ" => Some(PaymentType::OnAccount),
+        _ => None,
+    }
+}
+
+pub(crate) fn children<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Looks up elements by a `/` separated path of local names, ignoring namespaces
+pub(crate) fn find<'a, 'input>(node: Node<'a, 'input>, path: &str) -> Option<Node<'a, 'input>> {
+    path.split('/')
+        .try_fold(node, |node, name| node.children().find(|child| child.is_element() && child.tag_name().name() == name))
+}
+
+pub(crate) struct Xml<'a, 'input> {
+    pub(crate) doc: &'a Document<'input>,
+}
+
+impl<'a, 'input> Xml<'a, 'input> {
+    pub(crate) fn line(&self, node: Node) -> usize {
+        self.doc.text_pos_at(node.range().start).row as usize
+    }
+
+    pub(crate) fn required(&self, node: Node<'a, 'input>, path: &str) -> Result<Node<'a, 'input>, InvoiceParseError> {
+        find(node, path).ok_or_else(|| InvoiceParseError::XmlElementMissingError(path.to_string(), self.line(node)))
+    }
+
+    pub(crate) fn optional_text(&self, node: Node<'a, 'input>, path: &str) -> Option<&'a str> {
+        find(node, path).and_then(|n| n.text()).map(str::trim)
+    }
+
+    pub(crate) fn text(&self, node: Node<'a, 'input>, path: &str) -> Result<&'a str, InvoiceParseError> {
+        self.optional_text(node, path)
+            .ok_or_else(|| InvoiceParseError::XmlElementMissingError(path.to_string(), self.line(node)))
+    }
+
+    pub(crate) fn optional_decimal(&self, node: Node<'a, 'input>, path: &str) -> Result<Option<BigDecimal>, InvoiceParseError> {
+        let Some(element) = find(node, path) else {
+            return Ok(None);
+        };
+        let text = element.text().unwrap_or("").trim();
+        BigDecimal::from_str(text)
+            .map(Some)
+            .map_err(|e| InvoiceParseError::InvalidFieldError(path.to_string(), text.to_string(), self.line(element), e.to_string()))
+    }
+
+    pub(crate) fn decimal(&self, node: Node<'a, 'input>, path: &str) -> Result<BigDecimal, InvoiceParseError> {
+        self.optional_decimal(node, path)?
+            .ok_or_else(|| InvoiceParseError::XmlElementMissingError(path.to_string(), self.line(node)))
+    }
+
+    /// Net price of a single unit, both syntaxes allow prices per e.g. 100 pieces
+    pub(crate) fn unit_price(&self, node: Node<'a, 'input>, price_path: &str, basis_path: &str) -> Result<BigDecimal, InvoiceParseError> {
+        let price = self.decimal(node, price_path)?;
+        match self.optional_decimal(node, basis_path)? {
+            Some(basis) if basis.is_zero() => Err(InvoiceParseError::InvalidFieldError(
+                basis_path.to_string(),
+                "0".to_string(),
+                self.line(node),
+                "price basis quantity of zero".to_string(),
+            )),
+            Some(basis) if !basis.is_one() => Ok((price / basis).round(NET_PRICE_SCALE)),
+            _ => Ok(price),
+        }
+    }
+}
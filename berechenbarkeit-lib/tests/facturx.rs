@@ -0,0 +1,68 @@
+//! ZUGFeRD / Factur-X PDFs have to be parsed from their embedded XML, whatever vendor their text belongs to.
+
+use std::fs;
+use std::path::Path;
+
+use berechenbarkeit_lib::vendors::cii::parse_cii;
+use berechenbarkeit_lib::vendors::facturx::embedded_invoice_xml;
+use berechenbarkeit_lib::{parse_pdf, Invoice};
+use lopdf::{dictionary, Document, Object, Stream};
+
+fn fixture_xml() -> String {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/xml/facturx-en16931.xml")).unwrap()
+}
+
+/// A blank one page PDF with `content` attached the way PDF/A-3 invoices do it
+fn pdf_with_attachment(name: &str, content: &[u8]) -> Vec<u8> {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }),
+    );
+    let mut file = Stream::new(dictionary! { "Type" => "EmbeddedFile", "Subtype" => "text/xml" }, content.to_vec());
+    file.compress().unwrap();
+    let file_id = doc.add_object(file);
+    let filespec_id = doc.add_object(dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::string_literal(name),
+        "UF" => Object::string_literal(name),
+        "EF" => dictionary! { "F" => file_id },
+        "AFRelationship" => "Alternative",
+    });
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "Names" => dictionary! { "EmbeddedFiles" => dictionary! { "Names" => vec![Object::string_literal(name), filespec_id.into()] } },
+        "AF" => vec![filespec_id.into()],
+    });
+    doc.trailer.set("Root", catalog_id);
+    let mut pdf = vec![];
+    doc.save_to(&mut pdf).unwrap();
+    pdf
+}
+
+fn to_json(invoice: &Invoice) -> String {
+    serde_json::to_string_pretty(invoice).unwrap()
+}
+
+#[test]
+fn embedded_xml_is_parsed() {
+    let xml = fixture_xml();
+    let pdf = pdf_with_attachment("factur-x.xml", xml.as_bytes());
+
+    let from_pdf = parse_pdf(&pdf, None).unwrap();
+    let from_xml = parse_cii(&xml).unwrap();
+    assert_eq!(to_json(&from_pdf), to_json(&from_xml));
+}
+
+#[test]
+fn other_attachments_are_ignored() {
+    let pdf = pdf_with_attachment("notes.xml", fixture_xml().as_bytes());
+    assert!(embedded_invoice_xml(&pdf).is_none());
+}
@@ -0,0 +1,107 @@
+//! Golden-file tests for the vendor parsers.
+//!
+//! Every `tests/fixtures/<vendor>/<case>.txt` holds the (anonymised) text `pdf_extract` produced for an invoice of
+//! that vendor, the `<case>.json` next to it the [`Invoice`] we expect to be parsed from it. XML invoices (CII and UBL),
+//! which don't depend on the vendor, live in `tests/fixtures/xml/<case>.xml`.
+//!
+//! After an intentional parser change, regenerate the expected files with
+//! `UPDATE_GOLDEN=1 cargo test -p berechenbarkeit-lib --test golden` and review the diff.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use berechenbarkeit_lib::vendors::xml::parse_xml;
+use berechenbarkeit_lib::{get_vendors, Invoice, InvoiceParser, InvoiceVendor};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+fn parse_fixture(vendor: &str, text: &str) -> Result<Invoice, String> {
+    let vendor = InvoiceVendor::try_from(vendor.to_string()).map_err(|e| e.to_string())?;
+    let parser = InvoiceParser::try_from(vendor).map_err(|e| e.to_string())?;
+    parser.extract_invoice_data_from_text(text).map_err(|e| e.to_string())
+}
+
+fn cases(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut cases: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|_| panic!("no fixtures in {}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no fixtures in {}", dir.display());
+    cases
+}
+
+/// Compares (or with `update` writes) the expected JSON of `case`, problems are added to `failures`
+fn check_case(case: &Path, parsed: Result<Invoice, String>, update: bool, failures: &mut Vec<String>) {
+    let expected_path = case.with_extension("json");
+    let actual = match parsed {
+        Ok(invoice) => serde_json::to_string_pretty(&invoice).unwrap() + "\n",
+        Err(e) => {
+            failures.push(format!("{}: failed to parse: {}", case.display(), e));
+            return;
+        }
+    };
+
+    if update {
+        fs::write(&expected_path, &actual).unwrap();
+        return;
+    }
+    match fs::read_to_string(&expected_path) {
+        Ok(expected) if expected == actual => {}
+        Ok(expected) => failures.push(format!("{}: output differs\n{}", case.display(), diff(&expected, &actual))),
+        Err(e) => failures.push(format!("{}: {} ({})", expected_path.display(), e, "run with UPDATE_GOLDEN=1 to create it")),
+    }
+}
+
+fn update_requested() -> bool {
+    std::env::var("UPDATE_GOLDEN").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Line based diff, good enough for pretty printed JSON of the same shape
+fn diff(expected: &str, actual: &str) -> String {
+    let (expected, actual): (Vec<_>, Vec<_>) = (expected.lines().collect(), actual.lines().collect());
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e != a {
+            if let Some(e) = e {
+                out.push_str(&format!("{:>5} - {}\n", i + 1, e));
+            }
+            if let Some(a) = a {
+                out.push_str(&format!("{:>5} + {}\n", i + 1, a));
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn golden_files() {
+    let update = update_requested();
+    let mut failures: Vec<String> = vec![];
+
+    for vendor in get_vendors() {
+        for case in cases(&fixtures_dir().join(vendor.to_lowercase()), "txt") {
+            let text = fs::read_to_string(&case).unwrap();
+            check_case(&case, parse_fixture(&vendor.to_lowercase(), &text), update, &mut failures);
+        }
+    }
+
+    assert!(failures.is_empty(), "{} golden file(s) failed:\n\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn golden_xml_files() {
+    let update = update_requested();
+    let mut failures: Vec<String> = vec![];
+
+    for case in cases(&fixtures_dir().join("xml"), "xml") {
+        let xml = fs::read_to_string(&case).unwrap();
+        check_case(&case, parse_xml(&xml).map_err(|e| e.to_string()), update, &mut failures);
+    }
+
+    assert!(failures.is_empty(), "{} golden file(s) failed:\n\n{}", failures.len(), failures.join("\n"));
+}
@@ -0,0 +1,28 @@
+//! A VAT subtotal line that can't be read only weakens the validation, the items of the invoice are still imported.
+
+use std::fs;
+use std::path::Path;
+
+use berechenbarkeit_lib::validation::ValidationStatus;
+use berechenbarkeit_lib::{InvoiceParser, InvoiceVendor};
+
+fn parse_metro(text: &str) -> berechenbarkeit_lib::Invoice {
+    let parser = InvoiceParser::try_from(InvoiceVendor::try_from("Metro".to_string()).unwrap()).unwrap();
+    parser.extract_invoice_data_from_text(text).unwrap()
+}
+
+#[test]
+fn unreadable_subtotal_is_a_validation_problem() {
+    let text = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metro/default.txt")).unwrap();
+    let complete = parse_metro(&text);
+    assert!(complete.validation.problems.is_empty());
+
+    // "C" is no VAT class of Metro
+    let broken = parse_metro(&text.replacen("\nB     48,60", "\nC     48,60", 1));
+    assert_eq!(broken.items.len(), complete.items.len());
+    assert_eq!(broken.meta.vat_subtotals.len(), complete.meta.vat_subtotals.len() - 1);
+    assert_eq!(broken.validation.problems.len(), 1);
+    assert!(broken.validation.problems[0].contains("'C'"), "{}", broken.validation.problems[0]);
+    // The items of the skipped rate have no subtotal to be checked against
+    assert_eq!(broken.validation.status, ValidationStatus::MissingItems);
+}
@@ -0,0 +1,90 @@
+//! Vendor definition files from a directory are checked when loaded, and the errors name the offending file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use berechenbarkeit_lib::vendors::{load_regex_vendors, VendorDefinitionError};
+
+const METRO: &str = include_str!("../src/vendors/definitions/metro.toml");
+
+/// An empty directory only used by the test called `name`
+fn definitions_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("berechenbarkeit-vendor-definitions-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, file: &str, definition: &str) -> String {
+    let path = dir.join(file);
+    fs::write(&path, definition).unwrap();
+    path.display().to_string()
+}
+
+fn renamed_metro(name: &str) -> String {
+    METRO.replacen(r#"name = "Metro""#, &format!(r#"name = "{}""#, name), 1)
+}
+
+#[test]
+fn missing_capture_group() {
+    let dir = definitions_dir("missing-capture-group");
+    let source = write(&dir, "neu.toml", &renamed_metro("Neu").replace("(?P<INVOICE_NUMBER>", "(?P<NUMMER>"));
+    match load_regex_vendors(Some(&dir)) {
+        Err(VendorDefinitionError::MissingCaptureGroupError(file, field, group)) => {
+            assert_eq!(file, source);
+            assert_eq!(field, "invoice_number.re");
+            assert_eq!(group, "INVOICE_NUMBER");
+        }
+        other => panic!("expected a missing capture group, got {:?}", other.map(|vendors| vendors.len())),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_vat_rate() {
+    let dir = definitions_dir("invalid-vat-rate");
+    let source = write(&dir, "neu.toml", &renamed_metro("Neu").replace(r#""A" = "0.19""#, r#""A" = "19 %""#));
+    match load_regex_vendors(Some(&dir)) {
+        Err(VendorDefinitionError::InvalidVatRateError(file, field, value)) => {
+            assert_eq!(file, source);
+            assert_eq!(field, "vat_classes.A");
+            assert_eq!(value, "19 %");
+        }
+        other => panic!("expected an invalid VAT rate, got {:?}", other.map(|vendors| vendors.len())),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn duplicate_vendor_across_files() {
+    let dir = definitions_dir("duplicate-vendor");
+    write(&dir, "a.toml", &renamed_metro("Neu"));
+    let source = write(&dir, "b.toml", &renamed_metro("Neu"));
+    match load_regex_vendors(Some(&dir)) {
+        Err(VendorDefinitionError::DuplicateVendorError(file, vendor)) => {
+            assert_eq!(file, source);
+            assert_eq!(vendor, "Neu");
+        }
+        other => panic!("expected a duplicate vendor, got {:?}", other.map(|vendors| vendors.len())),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn override_bundled_vendor() {
+    let text = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metro/default.txt")).unwrap();
+    let bundled = load_regex_vendors(None).unwrap();
+    assert!(bundled.iter().find(|vendor| vendor.name == "Metro").unwrap().get_meta(&text).is_ok());
+
+    let dir = definitions_dir("override");
+    write(&dir, "metro.toml", &METRO.replace("RECHNUNGS?-? ?NR", "BELEGNUMMER"));
+    write(&dir, "neu.toml", &renamed_metro("Neu"));
+    let vendors = load_regex_vendors(Some(&dir)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // Metro is replaced in place, Neu comes in addition
+    assert_eq!(vendors.len(), bundled.len() + 1);
+    assert_eq!(vendors.iter().filter(|vendor| vendor.name == "Metro").count(), 1);
+    assert!(vendors.iter().find(|vendor| vendor.name == "Metro").unwrap().get_meta(&text).is_err());
+    assert!(vendors.iter().find(|vendor| vendor.name == "Neu").unwrap().get_meta(&text).is_ok());
+}
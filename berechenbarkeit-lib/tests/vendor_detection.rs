@@ -0,0 +1,54 @@
+//! Vendor detection has to give up instead of guessing when no vendor or more than one matches equally well.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use berechenbarkeit_lib::vendors::init_regex_vendors;
+use berechenbarkeit_lib::{detect_vendor_from_text, InvoiceParseError, InvoiceVendor};
+
+static INIT: Once = Once::new();
+
+fn fixture(path: &str) -> String {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(path)).unwrap()
+}
+
+/// The bundled vendors plus "Metro Kopie", which matches Metro invoices exactly as well as Metro does
+fn init() {
+    INIT.call_once(|| {
+        let dir: PathBuf = std::env::temp_dir().join(format!("berechenbarkeit-vendor-detection-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let metro = include_str!("../src/vendors/definitions/metro.toml");
+        fs::write(dir.join("metro-kopie.toml"), metro.replacen(r#"name = "Metro""#, r#"name = "Metro Kopie""#, 1)).unwrap();
+        init_regex_vendors(Some(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    });
+}
+
+fn vendor(name: &str) -> InvoiceVendor {
+    InvoiceVendor::try_from(name.to_string()).unwrap()
+}
+
+#[test]
+fn detects_the_only_matching_vendor() {
+    init();
+    assert_eq!(detect_vendor_from_text(&fixture("ikea/default.txt")), Ok(vendor("IKEA")));
+}
+
+#[test]
+fn no_match_is_an_error() {
+    init();
+    assert_eq!(
+        detect_vendor_from_text("Hallo Welt, das ist keine Rechnung"),
+        Err(InvoiceParseError::VendorNotDetectedError)
+    );
+}
+
+#[test]
+fn tie_is_an_error() {
+    init();
+    assert_eq!(
+        detect_vendor_from_text(&fixture("metro/default.txt")),
+        Err(InvoiceParseError::AmbiguousVendorError(vec![vendor("Metro"), vendor("Metro Kopie")]))
+    );
+}
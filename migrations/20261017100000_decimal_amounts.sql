@@ -0,0 +1,10 @@
+-- Store money amounts, quantities and VAT rates exactly
+-- The old float values are rounded on conversion, so float noise (e.g. 0.30000000000000004) doesn't become permanent:
+-- sums to cents, single net prices to the 4 decimals the parsers calculate net prices with, quantities and VAT rates
+-- to 4 decimals (e.g. 1.125 kg, 0.055) without trailing zeros
+ALTER TABLE invoice ALTER COLUMN sum_gross TYPE NUMERIC USING ROUND(sum_gross::numeric, 2);
+
+ALTER TABLE invoice_item
+    ALTER COLUMN amount TYPE NUMERIC USING TRIM_SCALE(ROUND(amount::numeric, 4)),
+    ALTER COLUMN net_price_single TYPE NUMERIC USING TRIM_SCALE(ROUND(net_price_single::numeric, 4)),
+    ALTER COLUMN vat TYPE NUMERIC USING TRIM_SCALE(ROUND(vat::numeric, 4));
@@ -0,0 +1,5 @@
+-- Keep what was bought in which pack size, for reordering
+ALTER TABLE invoice_item
+    ADD COLUMN article_number VARCHAR,
+    ADD COLUMN ean VARCHAR,
+    ADD COLUMN packaging_unit_amount NUMERIC;
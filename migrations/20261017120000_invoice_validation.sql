@@ -0,0 +1,16 @@
+-- Result of cross-checking the totals printed on an invoice with its parsed items at upload time
+ALTER TABLE invoice ADD COLUMN validation_status VARCHAR NULL;
+
+CREATE TABLE invoice_vat_check
+(
+    id                 BIGSERIAL PRIMARY KEY,
+    invoice_id         BIGINT    NOT NULL REFERENCES invoice (id) ON DELETE CASCADE,
+    vat                NUMERIC   NOT NULL,
+    printed_net        NUMERIC   NULL,
+    items_net          NUMERIC   NOT NULL,
+    printed_vat_amount NUMERIC   NULL,
+    items_vat_amount   NUMERIC   NOT NULL,
+    status             VARCHAR   NOT NULL
+);
+
+CREATE INDEX invoice_vat_check_invoice_id_idx ON invoice_vat_check (invoice_id);
@@ -0,0 +1,2 @@
+-- Invoices can be uploaded as PDF or as XRechnung XML, stored as invoice-<id>.<document_type>
+ALTER TABLE invoice ADD COLUMN document_type VARCHAR NOT NULL DEFAULT 'pdf';
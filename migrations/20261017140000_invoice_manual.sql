@@ -0,0 +1,3 @@
+-- Invoices entered by hand instead of parsed from an upload, their attachment is optional
+ALTER TABLE invoice ADD COLUMN manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE invoice ALTER COLUMN document_type DROP NOT NULL;
@@ -0,0 +1,33 @@
+-- Values as produced by the parser (or the manual entry form), edited fields are the ones differing from these.
+-- Items added by hand on the edit page have no original values.
+ALTER TABLE invoice
+    ADD COLUMN original_vendor         VARCHAR   NULL,
+    ADD COLUMN original_invoice_number VARCHAR   NULL,
+    ADD COLUMN original_sum_gross      NUMERIC   NULL,
+    ADD COLUMN original_date           TIMESTAMP NULL,
+    ADD COLUMN original_payment_type   VARCHAR   NULL;
+
+UPDATE invoice
+SET original_vendor         = vendor,
+    original_invoice_number = invoice_number,
+    original_sum_gross      = sum_gross,
+    original_date           = date,
+    original_payment_type   = payment_type;
+
+ALTER TABLE invoice
+    ALTER COLUMN original_vendor SET NOT NULL,
+    ALTER COLUMN original_invoice_number SET NOT NULL,
+    ALTER COLUMN original_sum_gross SET NOT NULL,
+    ALTER COLUMN original_date SET NOT NULL;
+
+ALTER TABLE invoice_item
+    ADD COLUMN original_description      VARCHAR NULL,
+    ADD COLUMN original_amount           NUMERIC NULL,
+    ADD COLUMN original_net_price_single NUMERIC NULL,
+    ADD COLUMN original_vat              NUMERIC NULL;
+
+UPDATE invoice_item
+SET original_description      = description,
+    original_amount           = amount,
+    original_net_price_single = net_price_single,
+    original_vat              = vat;
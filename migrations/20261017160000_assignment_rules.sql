@@ -0,0 +1,16 @@
+CREATE TABLE assignment_rule
+(
+    id                  BIGSERIAL   PRIMARY KEY,
+    name                VARCHAR     NOT NULL,
+    priority            BIGINT      NOT NULL,
+    vendor              VARCHAR     NULL,
+    article_number      VARCHAR     NULL,
+    description_regex   VARCHAR     NULL,
+    vat                 NUMERIC     NULL,
+    price_min           NUMERIC     NULL,
+    price_max           NUMERIC     NULL,
+    cost_centre_id      BIGINT      NULL REFERENCES cost_centre (id) ON DELETE SET NULL,
+    project_id          BIGINT      NULL REFERENCES project (id) ON DELETE SET NULL
+);
+
+CREATE INDEX assignment_rule_priority_idx ON assignment_rule (priority);
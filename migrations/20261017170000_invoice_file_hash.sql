@@ -0,0 +1,3 @@
+ALTER TABLE invoice ADD COLUMN file_sha256 VARCHAR NULL;
+CREATE INDEX invoice_file_sha256_idx ON invoice (file_sha256);
+CREATE INDEX invoice_vendor_invoice_number_idx ON invoice (LOWER(vendor), invoice_number);
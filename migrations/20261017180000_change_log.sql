@@ -0,0 +1,65 @@
+-- Append-only log of all changes to invoices, items, cost centres and projects, one row per changed field
+CREATE TABLE change_log
+(
+    id              BIGSERIAL   PRIMARY KEY,
+    changed_at      TIMESTAMP   NOT NULL DEFAULT LOCALTIMESTAMP,
+    author          VARCHAR     NULL,
+    table_name      VARCHAR     NOT NULL,
+    record_id       BIGINT      NOT NULL,
+    -- No foreign key, the history of an invoice outlives it
+    invoice_id      BIGINT      NULL,
+    action          VARCHAR     NOT NULL,
+    field           VARCHAR     NULL,
+    old_value       TEXT        NULL,
+    new_value       TEXT        NULL
+);
+
+CREATE INDEX change_log_invoice_id_idx ON change_log (invoice_id);
+CREATE INDEX change_log_record_idx ON change_log (table_name, record_id);
+
+-- Inserts and deletes are logged with the field given as argument (e.g. the name) so they can be told apart later,
+-- updates with every field that changed. The application sets berechenbarkeit.author for every connection it hands out.
+CREATE FUNCTION log_change() RETURNS TRIGGER AS $$
+DECLARE
+    v_old JSONB := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+    v_new JSONB := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+    v_record JSONB := COALESCE(v_new, v_old);
+    v_author VARCHAR := NULLIF(current_setting('berechenbarkeit.author', true), '');
+    v_invoice_id BIGINT := CASE TG_TABLE_NAME
+        WHEN 'invoice' THEN (v_record->>'id')::BIGINT
+        WHEN 'invoice_item' THEN (v_record->>'invoice_id')::BIGINT
+    END;
+    v_field TEXT;
+BEGIN
+    IF TG_OP = 'UPDATE' THEN
+        FOR v_field IN SELECT jsonb_object_keys(v_new) LOOP
+            IF v_field <> 'id' AND v_new->v_field IS DISTINCT FROM v_old->v_field THEN
+                INSERT INTO change_log (author, table_name, record_id, invoice_id, action, field, old_value, new_value)
+                VALUES (v_author, TG_TABLE_NAME, (v_record->>'id')::BIGINT, v_invoice_id, 'update', v_field, v_old->>v_field, v_new->>v_field);
+            END IF;
+        END LOOP;
+    ELSE
+        INSERT INTO change_log (author, table_name, record_id, invoice_id, action, field, old_value, new_value)
+        VALUES (v_author, TG_TABLE_NAME, (v_record->>'id')::BIGINT, v_invoice_id, lower(TG_OP), TG_ARGV[0], v_old->>TG_ARGV[0], v_new->>TG_ARGV[0]);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER invoice_change_log AFTER INSERT OR UPDATE OR DELETE ON invoice
+    FOR EACH ROW EXECUTE FUNCTION log_change('invoice_number');
+CREATE TRIGGER invoice_item_change_log AFTER INSERT OR UPDATE OR DELETE ON invoice_item
+    FOR EACH ROW EXECUTE FUNCTION log_change('description');
+CREATE TRIGGER cost_centre_change_log AFTER INSERT OR UPDATE OR DELETE ON cost_centre
+    FOR EACH ROW EXECUTE FUNCTION log_change('name');
+CREATE TRIGGER project_change_log AFTER INSERT OR UPDATE OR DELETE ON project
+    FOR EACH ROW EXECUTE FUNCTION log_change('name');
+
+CREATE FUNCTION change_log_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'change_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER change_log_append_only BEFORE UPDATE OR DELETE ON change_log
+    FOR EACH ROW EXECUTE FUNCTION change_log_append_only();
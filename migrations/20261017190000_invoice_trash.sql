@@ -0,0 +1,3 @@
+-- Deleted invoices stay in the trash until they're purged
+ALTER TABLE invoice ADD COLUMN deleted_at TIMESTAMP NULL;
+CREATE INDEX invoice_deleted_at_idx ON invoice (deleted_at);
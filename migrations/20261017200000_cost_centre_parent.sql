@@ -0,0 +1,15 @@
+-- Cost centres form a tree, e.g. "GPN > Catering > Drinks"
+ALTER TABLE cost_centre ADD COLUMN parent_id BIGINT NULL REFERENCES cost_centre (id) ON DELETE SET NULL;
+ALTER TABLE cost_centre ADD CONSTRAINT cost_centre_parent_not_self CHECK (parent_id <> id);
+CREATE INDEX cost_centre_parent_id_idx ON cost_centre (parent_id);
+
+-- Children of a deleted cost centre move up to its parent, within the same statement so a failing delete leaves them be
+CREATE FUNCTION cost_centre_reparent_children() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE cost_centre SET parent_id = OLD.parent_id WHERE parent_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER cost_centre_reparent_children BEFORE DELETE ON cost_centre
+    FOR EACH ROW EXECUTE FUNCTION cost_centre_reparent_children();
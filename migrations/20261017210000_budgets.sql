@@ -0,0 +1,14 @@
+-- Planned net spending for a cost centre (including the ones below it), a project, or a cost centre within a project
+CREATE TABLE budget
+(
+    id              BIGSERIAL   PRIMARY KEY,
+    name            VARCHAR     NOT NULL,
+    cost_centre_id  BIGINT      NULL REFERENCES cost_centre (id) ON DELETE CASCADE,
+    project_id      BIGINT      NULL REFERENCES project (id) ON DELETE CASCADE,
+    amount          NUMERIC     NOT NULL CHECK (amount >= 0),
+    -- Only items of invoices dated within the period count, both ends included
+    period_start    DATE        NULL,
+    period_end      DATE        NULL,
+    CHECK (cost_centre_id IS NOT NULL OR project_id IS NOT NULL),
+    CHECK (period_start <= period_end)
+);
@@ -0,0 +1,2 @@
+-- Projects are deactivated once their end has passed, unless this is turned off
+ALTER TABLE project ADD COLUMN auto_deactivate BOOLEAN NOT NULL DEFAULT true;
@@ -0,0 +1,22 @@
+-- A cost centre can't be moved below itself. Parent changes take a lock first, so two concurrent moves can't build a cycle together.
+CREATE FUNCTION cost_centre_check_parent() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.parent_id IS NULL THEN
+        RETURN NEW;
+    END IF;
+    PERFORM pg_advisory_xact_lock(hashtext('cost_centre_parent'));
+    IF EXISTS (
+        WITH RECURSIVE above (id) AS (
+            SELECT NEW.parent_id
+            UNION SELECT cost_centre.parent_id FROM cost_centre JOIN above ON cost_centre.id = above.id WHERE cost_centre.parent_id IS NOT NULL
+        )
+        SELECT 1 FROM above WHERE id = NEW.id
+    ) THEN
+        RAISE EXCEPTION 'cost centre % can''t be moved below itself', NEW.id USING ERRCODE = 'check_violation';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER cost_centre_check_parent BEFORE INSERT OR UPDATE OF parent_id ON cost_centre
+    FOR EACH ROW EXECUTE FUNCTION cost_centre_check_parent();
@@ -0,0 +1,31 @@
+-- The items counted by summaries, exports and budgets with their net, VAT and gross amounts: those of invoices not in the trash
+-- matching every filter argument that is set. The vendor is compared case-insensitively, the cost centre includes the ones below it.
+CREATE FUNCTION filtered_invoice_item(
+    p_from DATE,
+    p_until DATE,
+    p_vendor VARCHAR,
+    p_project_id BIGINT,
+    p_cost_centre_id BIGINT,
+    p_vat_exempt BOOLEAN
+) RETURNS TABLE (id BIGINT, net NUMERIC, vat_amount NUMERIC, gross NUMERIC) AS $$
+    SELECT
+        invoice_item.id,
+        invoice_item.amount * invoice_item.net_price_single,
+        invoice_item.amount * invoice_item.net_price_single * invoice_item.vat,
+        invoice_item.amount * invoice_item.net_price_single * (1 + invoice_item.vat)
+    FROM invoice_item
+    JOIN invoice ON invoice_item.invoice_id = invoice.id
+    WHERE invoice.deleted_at IS NULL
+        AND (p_from IS NULL OR invoice.date::DATE >= p_from)
+        AND (p_until IS NULL OR invoice.date::DATE <= p_until)
+        AND (p_vendor IS NULL OR LOWER(invoice.vendor) = LOWER(p_vendor))
+        AND (p_project_id IS NULL OR invoice_item.project_id = p_project_id)
+        AND (p_cost_centre_id IS NULL OR invoice_item.cost_centre_id IN (
+            WITH RECURSIVE below (id) AS (
+                SELECT p_cost_centre_id
+                UNION SELECT cost_centre.id FROM cost_centre JOIN below ON cost_centre.parent_id = below.id
+            )
+            SELECT below.id FROM below
+        ))
+        AND (p_vat_exempt IS NULL OR invoice_item.vat_exempt = p_vat_exempt)
+$$ LANGUAGE sql STABLE;
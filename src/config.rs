@@ -1,6 +1,22 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
 #[derive(clap::Parser)]
 pub struct Config {
     /// The connection URL for the Postgres database this application should use.
     #[clap(long, env)]
     pub database_url: String,
+
+    /// Directory with additional vendor definition files (`*.toml`), which may also override the bundled ones.
+    #[clap(long, env)]
+    pub vendor_definitions_dir: Option<PathBuf>,
+
+    /// Addresses of the authenticating reverse proxies, separated by commas. Only requests from these addresses may name the
+    /// logged in user with the `Remote-User` or `X-Forwarded-User` header, so the proxy has to strip both from incoming requests.
+    #[clap(long, env, value_delimiter = ',')]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Users allowed to purge invoices from the trash, separated by commas.
+    #[clap(long, env = "BERECHENBARKEIT_ADMIN_USERS", value_delimiter = ',')]
+    pub admin_users: Vec<String>,
 }
@@ -0,0 +1,181 @@
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use serde::Serialize;
+use sqlx::PgConnection;
+use time::macros::format_description;
+use time::Date;
+
+use crate::db::cost_centres::DBCostCentre;
+use crate::db::util::DBResult;
+
+/// Planned net spending of a cost centre (including the ones below it), a project, or a cost centre within a project
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DBBudget {
+    pub id: Option<i64>,
+    pub name: String,
+    pub cost_centre_id: Option<i64>,
+    pub cost_centre: Option<String>,
+    pub project_id: Option<i64>,
+    pub project: Option<String>,
+    /// Net
+    pub amount: BigDecimal,
+    /// Invoice dates counting towards the budget, both ends included
+    pub period_start: Option<Date>,
+    pub period_end: Option<Date>,
+}
+
+impl DBBudget {
+    pub(crate) async fn get_by_id(id: i64, connection: &mut PgConnection) -> DBResult<DBBudget> {
+        sqlx::query_as!(
+            DBBudget,
+            r#"SELECT
+                budget.*,
+                cost_centre.name AS "cost_centre?",
+                project.name AS "project?"
+            FROM budget
+            LEFT OUTER JOIN cost_centre ON budget.cost_centre_id = cost_centre.id
+            LEFT OUTER JOIN project ON budget.project_id = project.id
+            WHERE budget.id = $1"#,
+            id
+        )
+        .fetch_one(connection)
+        .await
+    }
+
+    pub(crate) async fn insert(budget: &DBBudget, connection: &mut PgConnection) -> DBResult<i64> {
+        Ok(sqlx::query!(
+            r#"INSERT INTO "budget" (name, cost_centre_id, project_id, amount, period_start, period_end) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"#,
+            budget.name,
+            budget.cost_centre_id,
+            budget.project_id,
+            budget.amount,
+            budget.period_start,
+            budget.period_end,
+        )
+        .fetch_one(connection)
+        .await?
+        .id)
+    }
+
+    pub(crate) async fn update(budget: &DBBudget, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(
+            r#"UPDATE "budget" SET name = $2, cost_centre_id = $3, project_id = $4, amount = $5, period_start = $6, period_end = $7 WHERE id = $1"#,
+            budget.id.unwrap(),
+            budget.name,
+            budget.cost_centre_id,
+            budget.project_id,
+            budget.amount,
+            budget.period_start,
+            budget.period_end,
+        )
+        .execute(connection)
+        .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete(id: i64, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"DELETE FROM "budget" WHERE id=$1"#, id).execute(connection).await?;
+        Ok(())
+    }
+
+    /// Whether an item assigned to `cost_centre` and `project_id` on an invoice from `date` counts towards the budget
+    pub(crate) fn applies_to(&self, date: Date, cost_centre: Option<&DBCostCentre>, project_id: Option<i64>) -> bool {
+        self.period_start.map_or(true, |start| date >= start)
+            && self.period_end.map_or(true, |end| date <= end)
+            && self.project_id.map_or(true, |id| project_id == Some(id))
+            && self
+                .cost_centre_id
+                .map_or(true, |id| cost_centre.is_some_and(|cc| cc.id == id || cc.ancestor_ids.contains(&id)))
+    }
+
+    pub(crate) fn period_label(&self) -> String {
+        let format = format_description!("[day].[month].[year]");
+        let label = |date: Option<Date>| date.and_then(|d| d.format(&format).ok());
+        match (label(self.period_start), label(self.period_end)) {
+            (None, None) => "unbegrenzt".to_string(),
+            (Some(start), None) => format!("ab {}", start),
+            (None, Some(end)) => format!("bis {}", end),
+            (Some(start), Some(end)) => format!("{} – {}", start, end),
+        }
+    }
+}
+
+/// A budget compared with what was actually spent
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BudgetStatus {
+    pub budget: DBBudget,
+    pub spent: BigDecimal,
+}
+
+impl BudgetStatus {
+    pub(crate) async fn get_all(connection: &mut PgConnection) -> DBResult<Vec<BudgetStatus>> {
+        BudgetStatus::get(None, connection).await
+    }
+
+    /// The budgets that at least one item of the invoice counts towards
+    pub(crate) async fn get_by_invoice_id(invoice_id: i64, connection: &mut PgConnection) -> DBResult<Vec<BudgetStatus>> {
+        BudgetStatus::get(Some(invoice_id), connection).await
+    }
+
+    /// Spending of every budget in one go, counting the same items as the summaries filtered by the budget's period, project and cost centre.
+    /// Cost centre budgets count the items of the cost centres below too, project budgets also the items without a cost centre.
+    async fn get(invoice_id: Option<i64>, connection: &mut PgConnection) -> DBResult<Vec<BudgetStatus>> {
+        let rows = sqlx::query!(
+            r#"SELECT
+                budget.id,
+                budget.name,
+                budget.cost_centre_id,
+                cost_centre.name AS "cost_centre?",
+                budget.project_id,
+                project.name AS "project?",
+                budget.amount,
+                budget.period_start,
+                budget.period_end,
+                COALESCE(ROUND(SUM(filtered.net), 2), 0) AS "spent!"
+            FROM budget
+            LEFT OUTER JOIN cost_centre ON budget.cost_centre_id = cost_centre.id
+            LEFT OUTER JOIN project ON budget.project_id = project.id
+            LEFT OUTER JOIN LATERAL filtered_invoice_item(budget.period_start, budget.period_end, NULL, budget.project_id, budget.cost_centre_id, NULL)
+                AS filtered ON TRUE
+            LEFT OUTER JOIN invoice_item ON invoice_item.id = filtered.id
+            GROUP BY budget.id, cost_centre.name, project.name
+            HAVING $1::BIGINT IS NULL OR BOOL_OR(invoice_item.invoice_id = $1)
+            ORDER BY budget.period_start DESC NULLS LAST, budget.name, budget.id"#,
+            invoice_id,
+        )
+        .fetch_all(connection)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| BudgetStatus {
+                budget: DBBudget {
+                    id: Some(row.id),
+                    name: row.name,
+                    cost_centre_id: row.cost_centre_id,
+                    cost_centre: row.cost_centre,
+                    project_id: row.project_id,
+                    project: row.project,
+                    amount: row.amount,
+                    period_start: row.period_start,
+                    period_end: row.period_end,
+                },
+                spent: row.spent,
+            })
+            .collect())
+    }
+
+    pub(crate) fn remaining(&self) -> BigDecimal {
+        (&self.budget.amount - &self.spent).round(2)
+    }
+
+    pub(crate) fn exceeded(&self) -> bool {
+        self.spent > self.budget.amount
+    }
+
+    /// Share of the budget spent in percent, rounded down
+    pub(crate) fn percent(&self) -> i64 {
+        if self.budget.amount.is_zero() {
+            return if self.spent.is_zero() { 0 } else { 100 };
+        }
+        (&self.spent * BigDecimal::from(100) / &self.budget.amount).to_i64().unwrap_or_default()
+    }
+}
@@ -1,71 +1,148 @@
+use crate::db::summary::SummaryFilter;
 use crate::db::util::DBResult;
+use bigdecimal::{BigDecimal, Zero};
 use serde::Serialize;
 use sqlx::PgConnection;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct DBCostCentre {
     pub id: i64,
     pub name: String,
+    pub parent_id: Option<i64>,
+    /// Names from the root down to this cost centre, e.g. "GPN > Catering > Drinks"
+    pub path: String,
+    /// From the root down to the parent
+    pub ancestor_ids: Vec<i64>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct CostCentreWithSum {
-    pub cost_centre_name: String,
-    pub vat: f64,
-    pub sum_net: f64,
-    pub sum_vat_exempted: f64,
+    pub vat: BigDecimal,
+    pub sum_net: BigDecimal,
+    pub sum_vat_exempted: BigDecimal,
+}
+
+/// Sums of a cost centre including all cost centres below it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CostCentreSummary {
+    pub cost_centre: DBCostCentre,
+    pub has_children: bool,
+    /// Per VAT rate
+    pub sums: Vec<CostCentreWithSum>,
+    pub sum_net: BigDecimal,
+    pub sum_vat_exempted: BigDecimal,
 }
 
 impl DBCostCentre {
+    /// All cost centres depth first, siblings ordered by name
     pub(crate) async fn get_all(connection: &mut PgConnection) -> DBResult<Vec<DBCostCentre>> {
-        sqlx::query_as!(DBCostCentre, r#"SELECT id, name FROM "cost_centre" ORDER BY id ASC"#)
-            .fetch_all(connection)
-            .await
+        sqlx::query_as!(
+            DBCostCentre,
+            r#"WITH RECURSIVE tree (id, name, parent_id, path, ancestor_ids, sort_key) AS (
+                    SELECT id, name, parent_id, name::TEXT, ARRAY[]::BIGINT[], ARRAY[name]
+                    FROM cost_centre
+                    WHERE parent_id IS NULL
+                UNION ALL
+                    SELECT
+                        cost_centre.id,
+                        cost_centre.name,
+                        cost_centre.parent_id,
+                        tree.path || ' > ' || cost_centre.name,
+                        tree.ancestor_ids || tree.id,
+                        tree.sort_key || cost_centre.name
+                    FROM cost_centre
+                    JOIN tree ON cost_centre.parent_id = tree.id
+            )
+            SELECT id AS "id!", name AS "name!", parent_id, path AS "path!", ancestor_ids AS "ancestor_ids!" FROM tree ORDER BY sort_key, id"#
+        )
+        .fetch_all(connection)
+        .await
     }
 
-    pub(crate) async fn insert(name: &str, connection: &mut PgConnection) -> DBResult<i64> {
-        Ok(sqlx::query!(r#"INSERT INTO "cost_centre" (name) VALUES ($1) RETURNING id"#, name,)
+    pub(crate) async fn insert(name: &str, parent_id: Option<i64>, connection: &mut PgConnection) -> DBResult<i64> {
+        Ok(sqlx::query!(r#"INSERT INTO "cost_centre" (name, parent_id) VALUES ($1, $2) RETURNING id"#, name, parent_id)
             .fetch_one(connection)
             .await?
             .id)
     }
 
-    pub(crate) async fn update(id: i64, name: &str, connection: &mut PgConnection) -> DBResult<DBCostCentre> {
-        sqlx::query_as!(DBCostCentre, r#"UPDATE "cost_centre" SET "name" = $2 WHERE id = $1 RETURNING *"#, id, name,)
-            .fetch_one(connection)
-            .await
+    /// Changing the parent moves the whole sub-tree along. The database refuses to move a cost centre below itself with a check violation.
+    pub(crate) async fn update(id: i64, name: &str, parent_id: Option<i64>, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"UPDATE "cost_centre" SET "name" = $2, parent_id = $3 WHERE id = $1"#, id, name, parent_id)
+            .execute(connection)
+            .await?;
+        Ok(())
     }
 
+    /// The children of a deleted cost centre move up to its parent
     pub(crate) async fn delete(id: i64, connection: &mut PgConnection) -> DBResult<()> {
         sqlx::query!(r#"DELETE FROM "cost_centre" WHERE id=$1"#, id).execute(connection).await?;
         Ok(())
     }
 
-    pub(crate) async fn get_summary(connection: &mut PgConnection) -> DBResult<Vec<CostCentreWithSum>> {
-        Ok(sqlx::query!(
+    pub(crate) fn depth(&self) -> usize {
+        self.ancestor_ids.len()
+    }
+
+    /// Whether `other` is this cost centre or below it
+    pub(crate) fn contains(&self, other: &DBCostCentre) -> bool {
+        other.id == self.id || other.ancestor_ids.contains(&self.id)
+    }
+
+    /// Space separated ids of the ancestors, for collapsing rows in the browser
+    pub(crate) fn ancestor_ids_label(&self) -> String {
+        self.ancestor_ids.iter().map(i64::to_string).collect::<Vec<String>>().join(" ")
+    }
+
+    /// Sums of every cost centre with items, each including the cost centres below it
+    pub(crate) async fn get_summary(filter: &SummaryFilter, connection: &mut PgConnection) -> DBResult<Vec<CostCentreSummary>> {
+        let sums = sqlx::query!(
             r#"SELECT
-                    cost_centre.name AS cost_centre_name,
+                    invoice_item.cost_centre_id AS "cost_centre_id!",
                     invoice_item.vat AS vat,
-                    ROUND(SUM(invoice_item.amount::numeric * invoice_item.net_price_single::numeric), 3)::double precision AS sum_net,
-                    ROUND(SUM(
-                        CASE
-                            WHEN invoice_item.vat_exempt
-                            THEN (invoice_item.amount::numeric * invoice_item.net_price_single::numeric) else 0
-                        END), 3)::double precision as sum_vat_exempted
-                FROM cost_centre
-                JOIN invoice_item ON cost_centre.id=invoice_item.cost_centre_id
-                GROUP BY cost_centre_name, vat
-                ORDER BY cost_centre_name, vat"#
+                    ROUND(SUM(filtered.net), 2) AS sum_net,
+                    ROUND(SUM(CASE WHEN invoice_item.vat_exempt THEN filtered.net ELSE 0 END), 2) AS sum_vat_exempted
+                FROM filtered_invoice_item($1, $2, $3, $4, $5, $6) AS filtered
+                JOIN invoice_item ON invoice_item.id = filtered.id
+                WHERE invoice_item.cost_centre_id IS NOT NULL
+                GROUP BY invoice_item.cost_centre_id, invoice_item.vat"#,
+            filter.from,
+            filter.until,
+            filter.vendor,
+            filter.project_id,
+            filter.cost_centre_id,
+            filter.vat_exempt,
         )
-        .fetch_all(connection)
-        .await?
-        .into_iter()
-        .map(|x| CostCentreWithSum {
-            cost_centre_name: x.cost_centre_name,
-            vat: x.vat,
-            sum_net: x.sum_net.unwrap_or(0f64),
-            sum_vat_exempted: x.sum_vat_exempted.unwrap_or(0f64),
-        })
-        .collect())
+        .fetch_all(&mut *connection)
+        .await?;
+        let cost_centres = DBCostCentre::get_all(connection).await?;
+        let by_id: HashMap<i64, &DBCostCentre> = cost_centres.iter().map(|cc| (cc.id, cc)).collect();
+
+        Ok(cost_centres
+            .iter()
+            .filter_map(|cost_centre| {
+                let mut by_vat: BTreeMap<BigDecimal, (BigDecimal, BigDecimal)> = BTreeMap::new();
+                for sum in sums.iter().filter(|sum| by_id.get(&sum.cost_centre_id).is_some_and(|cc| cost_centre.contains(cc))) {
+                    let (sum_net, sum_vat_exempted) = by_vat.entry(sum.vat.clone()).or_insert_with(|| (BigDecimal::zero(), BigDecimal::zero()));
+                    *sum_net += sum.sum_net.clone().unwrap_or_else(BigDecimal::zero);
+                    *sum_vat_exempted += sum.sum_vat_exempted.clone().unwrap_or_else(BigDecimal::zero);
+                }
+                if by_vat.is_empty() {
+                    return None;
+                }
+                let sums: Vec<CostCentreWithSum> = by_vat
+                    .into_iter()
+                    .map(|(vat, (sum_net, sum_vat_exempted))| CostCentreWithSum { vat, sum_net, sum_vat_exempted })
+                    .collect();
+                Some(CostCentreSummary {
+                    cost_centre: cost_centre.clone(),
+                    has_children: cost_centres.iter().any(|cc| cc.parent_id == Some(cost_centre.id)),
+                    sum_net: sums.iter().map(|sum| &sum.sum_net).sum(),
+                    sum_vat_exempted: sums.iter().map(|sum| &sum.sum_vat_exempted).sum(),
+                    sums,
+                })
+            })
+            .collect())
     }
 }
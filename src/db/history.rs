@@ -0,0 +1,93 @@
+use serde::Serialize;
+use sqlx::PgConnection;
+use time::macros::format_description;
+use time::PrimitiveDateTime;
+
+use crate::db::util::DBResult;
+
+/// One entry of the change log, written by database triggers
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DBChange {
+    pub id: i64,
+    pub changed_at: PrimitiveDateTime,
+    pub author: Option<String>,
+    pub table_name: String,
+    pub record_id: i64,
+    pub invoice_id: Option<i64>,
+    /// `insert`, `update` or `delete`
+    pub action: String,
+    /// The changed field for updates, the field identifying the record for inserts and deletes
+    pub field: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+impl DBChange {
+    pub(crate) async fn get_by_invoice_id(invoice_id: i64, connection: &mut PgConnection) -> DBResult<Vec<DBChange>> {
+        sqlx::query_as!(DBChange, r#"SELECT * FROM "change_log" WHERE invoice_id = $1 ORDER BY id DESC"#, invoice_id)
+            .fetch_all(connection)
+            .await
+    }
+
+    pub(crate) async fn get_latest(limit: i64, connection: &mut PgConnection) -> DBResult<Vec<DBChange>> {
+        sqlx::query_as!(DBChange, r#"SELECT * FROM "change_log" ORDER BY id DESC LIMIT $1"#, limit)
+            .fetch_all(connection)
+            .await
+    }
+
+    pub(crate) fn changed_at_label(&self) -> String {
+        self.changed_at
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn action_label(&self) -> &'static str {
+        match self.action.as_str() {
+            "insert" => "angelegt",
+            "delete" => "gelöscht",
+            _ => "geändert",
+        }
+    }
+
+    pub(crate) fn table_label(&self) -> &'static str {
+        match self.table_name.as_str() {
+            "invoice" => "Rechnung",
+            "invoice_item" => "Position",
+            "cost_centre" => "Kostenstelle",
+            "project" => "Projekt",
+            _ => "",
+        }
+    }
+
+    pub(crate) fn field_label(&self) -> String {
+        let Some(field) = &self.field else {
+            return "".to_string();
+        };
+        match field.as_str() {
+            "vendor" => "Händler",
+            "invoice_number" => "Rechnungsnummer",
+            "sum_gross" => "Summe (Brutto)",
+            "date" => "Datum",
+            "payment_type" => "Zahlungsart",
+            "description" => "Beschreibung",
+            "amount" => "Menge",
+            "net_price_single" => "Einzelpreis (Netto)",
+            "vat" => "MwSt",
+            "vat_exempt" => "MwSt-befreit",
+            "cost_centre_id" => "Kostenstelle",
+            "parent_id" => "Übergeordnete Kostenstelle",
+            "project_id" => "Projekt",
+            "typ" => "Art",
+            "position" => "Position",
+            "name" => "Name",
+            "active" => "Aktiv",
+            "default" => "Standard",
+            "start" => "Start",
+            "end" => "Ende",
+            "auto_deactivate" => "Automatisch deaktivieren",
+            "deleted_at" => "Im Papierkorb seit",
+            field => field,
+        }
+        .to_string()
+    }
+}
@@ -1,9 +1,13 @@
+use bigdecimal::{BigDecimal, Zero};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgConnection, Postgres, QueryBuilder};
+use time::macros::format_description;
 use time::PrimitiveDateTime;
 
+use crate::db::summary::SummaryFilter;
 use crate::db::util::DBResult;
-use berechenbarkeit_lib::Invoice;
+use berechenbarkeit_lib::validation::{ValidationStatus, VatRateCheck};
+use berechenbarkeit_lib::{Invoice, PaymentType};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct DBInvoice {
@@ -11,38 +15,221 @@ pub(crate) struct DBInvoice {
     pub id: Option<i64>,
     pub vendor: String,
     pub invoice_number: String,
-    pub sum_gross: f64,
+    pub sum_gross: BigDecimal,
     pub date: PrimitiveDateTime,
     pub payment_type: Option<String>,
+    pub validation_status: Option<String>,
+    /// File extension of the stored original (`pdf`, `xml`, `png` or `jpg`), `None` for manual invoices without attachment
+    pub document_type: Option<String>,
+    pub manual: bool,
+    /// SHA-256 of the uploaded file, to recognise it when it's uploaded again
+    pub file_sha256: Option<String>,
+    /// Set while the invoice is in the trash
+    pub deleted_at: Option<PrimitiveDateTime>,
+    pub original_vendor: String,
+    pub original_invoice_number: String,
+    pub original_sum_gross: BigDecimal,
+    pub original_date: PrimitiveDateTime,
+    pub original_payment_type: Option<String>,
 }
 
 impl DBInvoice {
     pub(crate) async fn get_all(connection: &mut PgConnection) -> DBResult<Vec<DBInvoice>> {
-        sqlx::query_as!(DBInvoice, r#"SELECT * FROM "invoice" ORDER BY date DESC"#).fetch_all(connection).await
+        sqlx::query_as!(DBInvoice, r#"SELECT * FROM "invoice" WHERE deleted_at IS NULL ORDER BY date DESC"#)
+            .fetch_all(connection)
+            .await
+    }
+
+    pub(crate) async fn get_vendors(connection: &mut PgConnection) -> DBResult<Vec<String>> {
+        Ok(sqlx::query!(r#"SELECT DISTINCT vendor FROM "invoice" WHERE deleted_at IS NULL ORDER BY vendor"#)
+            .fetch_all(connection)
+            .await?
+            .into_iter()
+            .map(|invoice| invoice.vendor)
+            .collect())
+    }
+
+    pub(crate) async fn get_trashed(connection: &mut PgConnection) -> DBResult<Vec<DBInvoice>> {
+        sqlx::query_as!(DBInvoice, r#"SELECT * FROM "invoice" WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC"#)
+            .fetch_all(connection)
+            .await
     }
 
     pub(crate) async fn get_by_id(id: i64, connection: &mut PgConnection) -> DBResult<DBInvoice> {
         sqlx::query_as!(DBInvoice, r#"SELECT * FROM "invoice" WHERE id=$1"#, id).fetch_one(connection).await
     }
 
+    /// Locks the invoice until the end of the transaction, so concurrent edits, trashing and restoring wait for each other
+    pub(crate) async fn get_by_id_for_update(id: i64, connection: &mut PgConnection) -> DBResult<DBInvoice> {
+        sqlx::query_as!(DBInvoice, r#"SELECT * FROM "invoice" WHERE id=$1 FOR UPDATE"#, id)
+            .fetch_one(connection)
+            .await
+    }
+
     pub(crate) async fn insert(object: DBInvoice, connection: &mut PgConnection) -> DBResult<i64> {
         Ok(sqlx::query!(
-            r#"INSERT INTO "invoice" (vendor, invoice_number, sum_gross, date, payment_type) VALUES ($1, $2, $3, $4, $5) RETURNING id"#,
+            r#"INSERT INTO "invoice" (vendor, invoice_number, sum_gross, date, payment_type, validation_status, document_type, manual, file_sha256,
+                original_vendor, original_invoice_number, original_sum_gross, original_date, original_payment_type)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $1, $2, $3, $4, $5) RETURNING id"#,
             object.vendor,
             object.invoice_number,
             object.sum_gross,
             object.date,
             object.payment_type,
+            object.validation_status,
+            object.document_type,
+            object.manual,
+            object.file_sha256,
         )
         .fetch_one(connection)
         .await?
         .id)
     }
 
+    /// Invoices which are the same file, have the same number or the same date and sum as `invoice` from the same vendor
+    pub(crate) async fn find_duplicates(invoice: &DBInvoice, connection: &mut PgConnection) -> DBResult<Vec<DBInvoice>> {
+        sqlx::query_as!(
+            DBInvoice,
+            r#"SELECT * FROM "invoice"
+            WHERE deleted_at IS NULL AND (
+                file_sha256 = $1
+                OR (LOWER(vendor) = LOWER($2) AND invoice_number = $3)
+                OR (LOWER(original_vendor) = LOWER($2) AND original_invoice_number = $3)
+                OR (LOWER(vendor) = LOWER($2) AND date::date = $4::timestamp::date AND sum_gross = $5)
+            )
+            ORDER BY date DESC"#,
+            invoice.file_sha256,
+            invoice.vendor,
+            invoice.invoice_number,
+            invoice.date,
+            invoice.sum_gross,
+        )
+        .fetch_all(connection)
+        .await
+    }
+
+    /// Why `self` looks like a duplicate of `other`
+    pub(crate) fn duplicate_reasons(&self, other: &DBInvoice) -> Vec<&'static str> {
+        let same_vendor = |vendor: &str| vendor.to_lowercase() == other.vendor.to_lowercase();
+        let mut reasons = vec![];
+        if self.file_sha256.is_some() && self.file_sha256 == other.file_sha256 {
+            reasons.push("gleiche Datei");
+        }
+        if (same_vendor(&self.vendor) && self.invoice_number == other.invoice_number)
+            || (same_vendor(&self.original_vendor) && self.original_invoice_number == other.invoice_number)
+        {
+            reasons.push("gleiche Rechnungsnummer");
+        }
+        if same_vendor(&self.vendor) && self.date.date() == other.date.date() && self.sum_gross == other.sum_gross {
+            reasons.push("gleiches Datum und gleiche Summe");
+        }
+        reasons
+    }
+
+    /// Moves the invoice to the trash, where it's left out of everything but the trash itself
+    pub(crate) async fn trash(id: i64, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"UPDATE "invoice" SET deleted_at = LOCALTIMESTAMP WHERE id=$1 AND deleted_at IS NULL"#, id)
+            .execute(connection)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn restore(id: i64, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"UPDATE "invoice" SET deleted_at = NULL WHERE id=$1"#, id).execute(connection).await?;
+        Ok(())
+    }
+
+    /// Removes the invoice with its items for good
     pub(crate) async fn delete(id: i64, connection: &mut PgConnection) -> DBResult<()> {
-        let result = sqlx::query!(r#"DELETE FROM invoice WHERE id=$1"#, id).execute(connection).await?;
+        sqlx::query!(r#"DELETE FROM invoice WHERE id=$1"#, id).execute(connection).await?;
         Ok(())
     }
+
+    /// Updates the header fields, the original values are kept
+    pub(crate) async fn update(object: &DBInvoice, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(
+            r#"UPDATE "invoice" SET vendor=$1, invoice_number=$2, sum_gross=$3, date=$4, payment_type=$5 WHERE id=$6"#,
+            object.vendor,
+            object.invoice_number,
+            object.sum_gross,
+            object.date,
+            object.payment_type,
+            object.id,
+        )
+        .execute(connection)
+        .await?;
+        Ok(())
+    }
+
+    pub(crate) fn vendor_edited(&self) -> bool {
+        self.vendor != self.original_vendor
+    }
+
+    pub(crate) fn invoice_number_edited(&self) -> bool {
+        self.invoice_number != self.original_invoice_number
+    }
+
+    pub(crate) fn sum_gross_edited(&self) -> bool {
+        self.sum_gross != self.original_sum_gross
+    }
+
+    pub(crate) fn date_edited(&self) -> bool {
+        self.date != self.original_date
+    }
+
+    pub(crate) fn payment_type_edited(&self) -> bool {
+        self.payment_type != self.original_payment_type
+    }
+
+    /// Label of the payment type the parser found, for showing next to an edited one
+    pub(crate) fn original_payment_type_label(&self) -> String {
+        match &self.original_payment_type {
+            Some(key) => PaymentType::from_key(key).map_or_else(|| key.clone(), |p| p.to_string()),
+            None => "unbekannt".to_string(),
+        }
+    }
+
+    /// Human readable payment type, values not known to [`PaymentType`] are shown as stored
+    pub(crate) fn payment_type_label(&self) -> String {
+        match &self.payment_type {
+            Some(key) => PaymentType::from_key(key).map_or_else(|| key.clone(), |p| p.to_string()),
+            None => "".to_string(),
+        }
+    }
+
+    pub(crate) fn validation_matched(&self) -> bool {
+        self.validation_status.as_deref() == Some(ValidationStatus::Matched.key())
+    }
+
+    /// Human readable result of the upload time validation, `None` for invoices uploaded before it existed
+    pub(crate) fn validation_status_label(&self) -> Option<String> {
+        let key = self.validation_status.as_ref()?;
+        Some(ValidationStatus::from_key(key).map_or_else(|| key.clone(), |s| s.to_string()))
+    }
+
+    /// XML invoices aren't readable as they are, they link to their HTML rendering instead
+    pub(crate) fn document_url(&self) -> Option<String> {
+        let id = self.id.unwrap_or_default();
+        match self.document_type.as_deref()? {
+            "xml" => Some(format!("/invoice/{}/view", id)),
+            "pdf" => Some(format!("/invoice/{}/pdf", id)),
+            _ => Some(format!("/invoice/{}/document", id)),
+        }
+    }
+
+    pub(crate) fn document_label(&self) -> &'static str {
+        match self.document_type.as_deref() {
+            Some("xml") => "XRechnung",
+            Some("pdf") => "PDF",
+            _ => "Beleg",
+        }
+    }
+
+    pub(crate) fn deleted_at_label(&self) -> String {
+        self.deleted_at
+            .and_then(|deleted_at| deleted_at.format(format_description!("[year]-[month]-[day] [hour]:[minute]")).ok())
+            .unwrap_or_default()
+    }
 }
 
 impl From<Invoice> for DBInvoice {
@@ -51,9 +238,19 @@ impl From<Invoice> for DBInvoice {
             id: None,
             vendor: invoice.vendor.to_string(),
             invoice_number: invoice.meta.invoice_number.clone(),
-            sum_gross: invoice.meta.sum_gross,
+            sum_gross: invoice.meta.sum_gross.clone(),
             date: invoice.meta.date,
-            payment_type: invoice.meta.payment_type.clone(),
+            payment_type: invoice.meta.payment_type.map(|p| p.key().to_string()),
+            validation_status: Some(invoice.validation.status.key().to_string()),
+            document_type: Some("pdf".to_string()),
+            manual: false,
+            file_sha256: None,
+            deleted_at: None,
+            original_vendor: invoice.vendor.to_string(),
+            original_invoice_number: invoice.meta.invoice_number.clone(),
+            original_sum_gross: invoice.meta.sum_gross.clone(),
+            original_date: invoice.meta.date,
+            original_payment_type: invoice.meta.payment_type.map(|p| p.key().to_string()),
         }
     }
 }
@@ -66,13 +263,20 @@ pub(crate) struct DBInvoiceItem {
     pub invoice_id: i64,
     pub typ: String,
     pub description: String,
-    pub amount: f64,
-    pub net_price_single: f64,
-    pub vat: f64,
+    pub amount: BigDecimal,
+    pub net_price_single: BigDecimal,
+    pub vat: BigDecimal,
     pub vat_exempt: bool,
     pub cost_centre_id: Option<i64>,
     pub cost_centre: Option<String>,
     pub project_id: Option<i64>,
+    pub article_number: Option<String>,
+    pub ean: Option<String>,
+    pub packaging_unit_amount: Option<BigDecimal>,
+    pub original_description: Option<String>,
+    pub original_amount: Option<BigDecimal>,
+    pub original_net_price_single: Option<BigDecimal>,
+    pub original_vat: Option<BigDecimal>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,58 +284,94 @@ pub(crate) struct InvoiceItemExtended {
     pub invoice_vendor: String,
     pub invoice_number: String,
     pub invoice_date: PrimitiveDateTime,
+    pub invoice_payment_type: Option<String>,
+    pub invoice_manual: bool,
     pub id: i64,
     pub position: i64,
     pub invoice_id: i64,
     pub typ: String,
     pub description: String,
-    pub amount: f64,
-    pub net_price_single: f64,
-    pub vat: f64,
+    pub amount: BigDecimal,
+    pub net_price_single: BigDecimal,
+    pub vat: BigDecimal,
     pub vat_exempt: bool,
     pub cost_centre_id: Option<i64>,
     pub cost_centre: Option<String>,
     pub project_id: Option<i64>,
+    pub project: Option<String>,
+    pub article_number: Option<String>,
+    pub ean: Option<String>,
+    pub packaging_unit_amount: Option<BigDecimal>,
+    pub original_description: Option<String>,
+    pub original_amount: Option<BigDecimal>,
+    pub original_net_price_single: Option<BigDecimal>,
+    pub original_vat: Option<BigDecimal>,
 }
 
 impl DBInvoiceItem {
     pub(crate) async fn bulk_insert(connection: &mut PgConnection, objects: Vec<DBInvoiceItem>) -> DBResult<()> {
-        let mut qb: QueryBuilder<Postgres> =
-            QueryBuilder::new("INSERT INTO invoice_item (position, invoice_id, typ, description, amount, net_price_single, vat, vat_exempt, cost_centre_id, project_id)");
+        if objects.is_empty() {
+            return Ok(());
+        }
+        let mut qb: QueryBuilder<Postgres> = QueryBuilder::new(
+            r#"INSERT INTO invoice_item (
+                position, invoice_id, typ, description, amount, net_price_single, vat, vat_exempt, cost_centre_id, project_id,
+                article_number, ean, packaging_unit_amount,
+                original_description, original_amount, original_net_price_single, original_vat
+            )"#,
+        );
         qb.push_values(objects.iter(), |mut b, rec| {
             b.push_bind(rec.position)
                 .push_bind(rec.invoice_id)
                 .push_bind(&rec.typ)
                 .push_bind(&rec.description)
-                .push_bind(rec.amount)
-                .push_bind(rec.net_price_single)
-                .push_bind(rec.vat)
+                .push_bind(&rec.amount)
+                .push_bind(&rec.net_price_single)
+                .push_bind(&rec.vat)
                 .push_bind(rec.vat_exempt)
                 .push_bind(rec.cost_centre_id)
-                .push_bind(rec.project_id);
+                .push_bind(rec.project_id)
+                .push_bind(&rec.article_number)
+                .push_bind(&rec.ean)
+                .push_bind(&rec.packaging_unit_amount)
+                .push_bind(&rec.original_description)
+                .push_bind(&rec.original_amount)
+                .push_bind(&rec.original_net_price_single)
+                .push_bind(&rec.original_vat);
         });
 
         qb.build().execute(connection).await?;
         Ok(())
     }
 
-    pub(crate) async fn get_all(connection: &mut PgConnection) -> DBResult<Vec<InvoiceItemExtended>> {
+    pub(crate) async fn get_all(filter: &SummaryFilter, connection: &mut PgConnection) -> DBResult<Vec<InvoiceItemExtended>> {
         sqlx::query_as!(
             InvoiceItemExtended,
             r#"SELECT
                 invoice.vendor AS invoice_vendor,
                 invoice.invoice_number,
                 invoice.date AS invoice_date,
+                invoice.payment_type AS invoice_payment_type,
+                invoice.manual AS invoice_manual,
                 invoice_item.*,
-                cost_centre.name AS "cost_centre?"
-            FROM invoice_item
-            LEFT OUTER JOIN cost_centre ON invoice_item.cost_centre_id = cost_centre.id
+                cost_centre.name AS "cost_centre?",
+                project.name AS "project?"
+            FROM filtered_invoice_item($1, $2, $3, $4, $5, $6) AS filtered
+            JOIN invoice_item ON invoice_item.id = filtered.id
             JOIN invoice ON invoice_item.invoice_id = invoice.id
+            LEFT OUTER JOIN cost_centre ON invoice_item.cost_centre_id = cost_centre.id
+            LEFT OUTER JOIN project ON invoice_item.project_id = project.id
             ORDER BY
                 invoice.date,
                 invoice.id,
                 invoice_item.position,
-                invoice_item.id"#
+                invoice_item.id"#,
+            filter.from,
+            filter.until,
+            filter.vendor,
+            filter.project_id,
+            filter.cost_centre_id,
+            filter.vat_exempt,
         )
         .fetch_all(connection)
         .await
@@ -169,7 +409,7 @@ impl DBInvoiceItem {
         .await
     }
 
-    pub(crate) async fn calculate_sum_gross_by_invoice_id(invoice_id: i64, connection: &mut PgConnection) -> DBResult<f64> {
+    pub(crate) async fn calculate_sum_gross_by_invoice_id(invoice_id: i64, connection: &mut PgConnection) -> DBResult<BigDecimal> {
         Ok(sqlx::query!(
             r#"SELECT
                 SUM(invoice_item.amount * invoice_item.net_price_single * (1 + invoice_item.vat))
@@ -180,11 +420,27 @@ impl DBInvoiceItem {
         .fetch_one(connection)
         .await?
         .sum
-        .unwrap_or(0f64))
+        .unwrap_or_else(BigDecimal::zero))
+    }
+
+    /// Updates the fields the parser produced, the original values are kept
+    pub(crate) async fn update(object: &DBInvoiceItem, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(
+            r#"UPDATE "invoice_item" SET typ=$1, description=$2, amount=$3, net_price_single=$4, vat=$5 WHERE id=$6"#,
+            object.typ,
+            object.description,
+            object.amount,
+            object.net_price_single,
+            object.vat,
+            object.id,
+        )
+        .execute(connection)
+        .await?;
+        Ok(())
     }
 
-    pub(crate) async fn update_amount(id: i64, amount: f64, connection: &mut PgConnection) -> DBResult<()> {
-        sqlx::query!(r#"UPDATE "invoice_item" SET amount=$1 WHERE id=$2"#, amount, id).execute(connection).await?;
+    pub(crate) async fn delete(id: i64, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"DELETE FROM "invoice_item" WHERE id=$1"#, id).execute(connection).await?;
         Ok(())
     }
 
@@ -195,13 +451,20 @@ impl DBInvoiceItem {
         Ok(())
     }
 
-    pub(crate) async fn update_project(id: i64, project: i64, connection: &mut PgConnection) -> DBResult<()> {
+    pub(crate) async fn update_project(id: i64, project: Option<i64>, connection: &mut PgConnection) -> DBResult<()> {
         sqlx::query!(r#"UPDATE "invoice_item" SET project_id = $2 WHERE ID = $1"#, id, project,)
             .execute(connection)
             .await?;
         Ok(())
     }
 
+    pub(crate) async fn update_original_amount(id: i64, original_amount: Option<BigDecimal>, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"UPDATE "invoice_item" SET original_amount=$1 WHERE id=$2"#, original_amount, id)
+            .execute(connection)
+            .await?;
+        Ok(())
+    }
+
     pub(crate) async fn update_vat_exemption(id: i64, vat_exempt: bool, connection: &mut PgConnection) -> DBResult<()> {
         sqlx::query!(r#"UPDATE "invoice_item" SET vat_exempt=$1 WHERE id=$2"#, vat_exempt, id)
             .execute(connection)
@@ -221,8 +484,15 @@ impl DBInvoiceItem {
                 vat,
                 vat_exempt,
                 cost_centre_id,
-                project_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id"#,
+                project_id,
+                article_number,
+                ean,
+                packaging_unit_amount,
+                original_description,
+                original_amount,
+                original_net_price_single,
+                original_vat)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) RETURNING id"#,
             object.position,
             object.invoice_id,
             object.typ,
@@ -233,9 +503,108 @@ impl DBInvoiceItem {
             object.vat_exempt,
             object.cost_centre_id,
             object.project_id,
+            object.article_number,
+            object.ean,
+            object.packaging_unit_amount,
+            object.original_description,
+            object.original_amount,
+            object.original_net_price_single,
+            object.original_vat,
         )
         .fetch_one(connection)
         .await?
         .id)
     }
+
+    /// Items added on the edit page have no values from the parser
+    pub(crate) fn added_manually(&self) -> bool {
+        self.original_description.is_none()
+    }
+
+    pub(crate) fn description_edited(&self) -> bool {
+        self.original_description.as_ref().is_some_and(|original| *original != self.description)
+    }
+
+    pub(crate) fn amount_edited(&self) -> bool {
+        self.original_amount.as_ref().is_some_and(|original| *original != self.amount)
+    }
+
+    pub(crate) fn net_price_single_edited(&self) -> bool {
+        self.original_net_price_single.as_ref().is_some_and(|original| *original != self.net_price_single)
+    }
+
+    pub(crate) fn vat_edited(&self) -> bool {
+        self.original_vat.as_ref().is_some_and(|original| *original != self.vat)
+    }
+
+    /// VAT rate in percent as entered on the edit page, rates are stored as fractions
+    pub(crate) fn vat_percent(&self) -> BigDecimal {
+        (&self.vat * BigDecimal::from(100)).normalized()
+    }
+
+    pub(crate) fn original_vat_percent(&self) -> Option<BigDecimal> {
+        self.original_vat.as_ref().map(|vat| (vat * BigDecimal::from(100)).normalized())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DBInvoiceVatCheck {
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub invoice_id: i64,
+    pub vat: BigDecimal,
+    pub printed_net: Option<BigDecimal>,
+    pub items_net: BigDecimal,
+    pub printed_vat_amount: Option<BigDecimal>,
+    pub items_vat_amount: BigDecimal,
+    pub status: String,
+}
+
+impl DBInvoiceVatCheck {
+    pub(crate) fn from_check(invoice_id: i64, check: VatRateCheck) -> Self {
+        DBInvoiceVatCheck {
+            id: None,
+            invoice_id,
+            vat: check.vat,
+            printed_net: check.printed_net,
+            items_net: check.items_net,
+            printed_vat_amount: check.printed_vat_amount,
+            items_vat_amount: check.items_vat_amount,
+            status: check.status.key().to_string(),
+        }
+    }
+
+    pub(crate) async fn bulk_insert(connection: &mut PgConnection, objects: Vec<DBInvoiceVatCheck>) -> DBResult<()> {
+        if objects.is_empty() {
+            return Ok(());
+        }
+        let mut qb: QueryBuilder<Postgres> =
+            QueryBuilder::new("INSERT INTO invoice_vat_check (invoice_id, vat, printed_net, items_net, printed_vat_amount, items_vat_amount, status)");
+        qb.push_values(objects.iter(), |mut b, rec| {
+            b.push_bind(rec.invoice_id)
+                .push_bind(&rec.vat)
+                .push_bind(&rec.printed_net)
+                .push_bind(&rec.items_net)
+                .push_bind(&rec.printed_vat_amount)
+                .push_bind(&rec.items_vat_amount)
+                .push_bind(&rec.status);
+        });
+
+        qb.build().execute(connection).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn get_by_invoice_id(invoice_id: i64, connection: &mut PgConnection) -> DBResult<Vec<DBInvoiceVatCheck>> {
+        sqlx::query_as!(DBInvoiceVatCheck, r#"SELECT * FROM "invoice_vat_check" WHERE invoice_id=$1 ORDER BY vat"#, invoice_id)
+            .fetch_all(connection)
+            .await
+    }
+
+    pub(crate) fn status_label(&self) -> String {
+        ValidationStatus::from_key(&self.status).map_or_else(|| self.status.clone(), |s| s.to_string())
+    }
+
+    pub(crate) fn is_matched(&self) -> bool {
+        self.status == ValidationStatus::Matched.key()
+    }
 }
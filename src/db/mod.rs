@@ -1,4 +1,9 @@
+pub mod budgets;
 pub mod cost_centres;
+pub mod history;
 pub mod invoices;
 pub mod projects;
+pub mod rules;
+pub mod suggestions;
+pub mod summary;
 pub mod util;
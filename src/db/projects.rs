@@ -1,6 +1,10 @@
+use bigdecimal::{BigDecimal, Zero};
 use serde::{Deserialize, Serialize};
 use sqlx::PgConnection;
+use time::macros::format_description;
+use time::Date;
 
+use crate::db::summary::SummaryFilter;
 use crate::db::util::{DBResult, DbDate};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +17,18 @@ pub(crate) struct DBProject {
     pub default: bool,
     pub start: DbDate,
     pub end: DbDate,
+    /// Deactivate the project once its end has passed
+    pub auto_deactivate: bool,
+}
+
+/// Sums of the items of one project assigned to one cost centre, either may be missing
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ProjectCostCentreSum {
+    pub project_id: Option<i64>,
+    pub cost_centre_id: Option<i64>,
+    pub sum_net: BigDecimal,
+    pub sum_vat: BigDecimal,
+    pub sum_gross: BigDecimal,
 }
 
 impl DBProject {
@@ -34,13 +50,14 @@ impl DBProject {
     pub(crate) async fn add(project: DBProject, conn: &mut PgConnection) -> DBResult<DBProject> {
         sqlx::query_as!(
             DBProject,
-            r#"INSERT INTO "project" (name, description, active, "default", "start", "end") VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"#,
+            r#"INSERT INTO "project" (name, description, active, "default", "start", "end", auto_deactivate) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *"#,
             project.name,
             project.description,
             project.active,
             project.default,
             project.start.datetime,
             project.end.datetime,
+            project.auto_deactivate,
         )
         .fetch_one(conn)
         .await
@@ -49,10 +66,10 @@ impl DBProject {
     pub(crate) async fn update(project: DBProject, conn: &mut PgConnection) -> DBResult<DBProject> {
         sqlx::query_as!(
             DBProject,
-            r#"INSERT INTO "project" (id, name, description, active, "default", "start", "end")
-                VALUES ($1, $2, $3, $4, $5, $6, $7)
+            r#"INSERT INTO "project" (id, name, description, active, "default", "start", "end", auto_deactivate)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
                 ON CONFLICT(id)
-                DO UPDATE SET name = $2, description = $3, active = $4, "default" = $5, "start" = $6, "end" = $7
+                DO UPDATE SET name = $2, description = $3, active = $4, "default" = $5, "start" = $6, "end" = $7, auto_deactivate = $8
                 RETURNING *"#,
             project.id.unwrap(),
             project.name,
@@ -61,6 +78,7 @@ impl DBProject {
             project.default,
             project.start.datetime,
             project.end.datetime,
+            project.auto_deactivate,
         )
         .fetch_one(conn)
         .await
@@ -81,4 +99,63 @@ impl DBProject {
         sqlx::query!(r#"UPDATE "project" SET "default" = false WHERE "default";"#).execute(conn).await?;
         Ok(())
     }
+
+    /// Deactivates projects whose end has passed, an ended default project stops being the default. Returns their names.
+    pub(crate) async fn deactivate_ended(conn: &mut PgConnection) -> DBResult<Vec<String>> {
+        Ok(
+            sqlx::query!(r#"UPDATE "project" SET active = false, "default" = false WHERE active AND auto_deactivate AND "end" < LOCALTIMESTAMP RETURNING name"#)
+                .fetch_all(conn)
+                .await?
+                .into_iter()
+                .map(|project| project.name)
+                .collect(),
+        )
+    }
+
+    /// Whether an invoice from `date` falls into the project's time range, open ends included
+    pub(crate) fn contains_date(&self, date: Date) -> bool {
+        self.start.datetime.map_or(true, |start| date >= start.date()) && self.end.datetime.map_or(true, |end| date <= end.date())
+    }
+
+    pub(crate) fn period_label(&self) -> String {
+        let format = format_description!("[day].[month].[year]");
+        let label = |date: &DbDate| date.datetime.and_then(|d| d.format(&format).ok());
+        match (label(&self.start), label(&self.end)) {
+            (None, None) => "unbegrenzt".to_string(),
+            (Some(start), None) => format!("ab {}", start),
+            (None, Some(end)) => format!("bis {}", end),
+            (Some(start), Some(end)) => format!("{} – {}", start, end),
+        }
+    }
+
+    pub(crate) async fn get_summary(filter: &SummaryFilter, conn: &mut PgConnection) -> DBResult<Vec<ProjectCostCentreSum>> {
+        Ok(sqlx::query!(
+            r#"SELECT
+                    invoice_item.project_id,
+                    invoice_item.cost_centre_id,
+                    ROUND(SUM(filtered.net), 2) AS sum_net,
+                    ROUND(SUM(filtered.vat_amount), 2) AS sum_vat,
+                    ROUND(SUM(filtered.gross), 2) AS sum_gross
+                FROM filtered_invoice_item($1, $2, $3, $4, $5, $6) AS filtered
+                JOIN invoice_item ON invoice_item.id = filtered.id
+                GROUP BY invoice_item.project_id, invoice_item.cost_centre_id"#,
+            filter.from,
+            filter.until,
+            filter.vendor,
+            filter.project_id,
+            filter.cost_centre_id,
+            filter.vat_exempt,
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|x| ProjectCostCentreSum {
+            project_id: x.project_id,
+            cost_centre_id: x.cost_centre_id,
+            sum_net: x.sum_net.unwrap_or_else(BigDecimal::zero),
+            sum_vat: x.sum_vat.unwrap_or_else(BigDecimal::zero),
+            sum_gross: x.sum_gross.unwrap_or_else(BigDecimal::zero),
+        })
+        .collect())
+    }
 }
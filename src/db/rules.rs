@@ -0,0 +1,340 @@
+use bigdecimal::BigDecimal;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use sqlx::PgConnection;
+
+use crate::db::invoices::DBInvoiceItem;
+use crate::db::projects::DBProject;
+use crate::db::util::DBResult;
+
+/// Assigns a cost centre and/or project to items matching all of its conditions
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DBAssignmentRule {
+    pub id: Option<i64>,
+    pub name: String,
+    /// Rules with a lower number are applied first
+    pub priority: i64,
+    pub vendor: Option<String>,
+    pub article_number: Option<String>,
+    pub description_regex: Option<String>,
+    pub vat: Option<BigDecimal>,
+    /// Range of the net price of a single unit, both ends included
+    pub price_min: Option<BigDecimal>,
+    pub price_max: Option<BigDecimal>,
+    pub cost_centre_id: Option<i64>,
+    pub cost_centre: Option<String>,
+    pub project_id: Option<i64>,
+    pub project: Option<String>,
+}
+
+impl DBAssignmentRule {
+    pub(crate) async fn get_all(connection: &mut PgConnection) -> DBResult<Vec<DBAssignmentRule>> {
+        sqlx::query_as!(
+            DBAssignmentRule,
+            r#"SELECT
+                assignment_rule.*,
+                cost_centre.name AS "cost_centre?",
+                project.name AS "project?"
+            FROM assignment_rule
+            LEFT OUTER JOIN cost_centre ON assignment_rule.cost_centre_id = cost_centre.id
+            LEFT OUTER JOIN project ON assignment_rule.project_id = project.id
+            ORDER BY assignment_rule.priority, assignment_rule.id"#
+        )
+        .fetch_all(connection)
+        .await
+    }
+
+    pub(crate) async fn get_by_id(id: i64, connection: &mut PgConnection) -> DBResult<DBAssignmentRule> {
+        sqlx::query_as!(
+            DBAssignmentRule,
+            r#"SELECT
+                assignment_rule.*,
+                cost_centre.name AS "cost_centre?",
+                project.name AS "project?"
+            FROM assignment_rule
+            LEFT OUTER JOIN cost_centre ON assignment_rule.cost_centre_id = cost_centre.id
+            LEFT OUTER JOIN project ON assignment_rule.project_id = project.id
+            WHERE assignment_rule.id = $1"#,
+            id
+        )
+        .fetch_one(connection)
+        .await
+    }
+
+    pub(crate) async fn insert(rule: &DBAssignmentRule, connection: &mut PgConnection) -> DBResult<i64> {
+        Ok(sqlx::query!(
+            r#"INSERT INTO "assignment_rule" (name, priority, vendor, article_number, description_regex, vat, price_min, price_max, cost_centre_id, project_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id"#,
+            rule.name,
+            rule.priority,
+            rule.vendor,
+            rule.article_number,
+            rule.description_regex,
+            rule.vat,
+            rule.price_min,
+            rule.price_max,
+            rule.cost_centre_id,
+            rule.project_id,
+        )
+        .fetch_one(connection)
+        .await?
+        .id)
+    }
+
+    pub(crate) async fn update(rule: &DBAssignmentRule, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(
+            r#"UPDATE "assignment_rule"
+                SET name = $2, priority = $3, vendor = $4, article_number = $5, description_regex = $6, vat = $7, price_min = $8, price_max = $9,
+                    cost_centre_id = $10, project_id = $11
+                WHERE id = $1"#,
+            rule.id.unwrap(),
+            rule.name,
+            rule.priority,
+            rule.vendor,
+            rule.article_number,
+            rule.description_regex,
+            rule.vat,
+            rule.price_min,
+            rule.price_max,
+            rule.cost_centre_id,
+            rule.project_id,
+        )
+        .execute(connection)
+        .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete(id: i64, connection: &mut PgConnection) -> DBResult<()> {
+        sqlx::query!(r#"DELETE FROM "assignment_rule" WHERE id=$1"#, id).execute(connection).await?;
+        Ok(())
+    }
+
+    pub(crate) fn description_regex(&self) -> Result<Option<Regex>, regex::Error> {
+        self.description_regex
+            .as_deref()
+            .map(|regex| RegexBuilder::new(regex).case_insensitive(true).build())
+            .transpose()
+    }
+
+    pub(crate) fn vat_percent(&self) -> Option<BigDecimal> {
+        self.vat.as_ref().map(|vat| (vat * BigDecimal::from(100)).normalized())
+    }
+
+    /// Every rule assigns something when saved, so a rule without cost centre and project had them deleted
+    /// (the references are set to NULL) and doesn't do anything anymore
+    pub(crate) fn is_broken(&self) -> bool {
+        self.cost_centre_id.is_none() && self.project_id.is_none()
+    }
+}
+
+/// A rule with its description regex compiled
+pub(crate) struct AssignmentRule {
+    pub rule: DBAssignmentRule,
+    description_regex: Option<Regex>,
+}
+
+impl AssignmentRule {
+    pub(crate) fn new(rule: DBAssignmentRule) -> Result<AssignmentRule, regex::Error> {
+        Ok(AssignmentRule {
+            description_regex: rule.description_regex()?,
+            rule,
+        })
+    }
+
+    /// Whether all conditions set on the rule hold for an item of an invoice from `vendor`
+    pub(crate) fn matches(&self, vendor: &str, article_number: Option<&str>, description: &str, vat: &BigDecimal, net_price_single: &BigDecimal) -> bool {
+        let rule = &self.rule;
+        rule.vendor.as_ref().map_or(true, |v| v.to_lowercase() == vendor.trim().to_lowercase())
+            && rule.article_number.as_ref().map_or(true, |a| Some(a.as_str()) == article_number.map(str::trim))
+            && self.description_regex.as_ref().map_or(true, |regex| regex.is_match(description))
+            && rule.vat.as_ref().map_or(true, |v| v == vat)
+            && rule.price_min.as_ref().map_or(true, |min| net_price_single >= min)
+            && rule.price_max.as_ref().map_or(true, |max| net_price_single <= max)
+    }
+
+    pub(crate) fn matches_item(&self, vendor: &str, item: &DBInvoiceItem) -> bool {
+        self.matches(vendor, item.article_number.as_deref(), &item.description, &item.vat, &item.net_price_single)
+    }
+}
+
+/// All rules in order of their priority, followed by the default project
+pub(crate) struct AssignmentRules {
+    rules: Vec<AssignmentRule>,
+    default_project_id: Option<i64>,
+}
+
+impl AssignmentRules {
+    pub(crate) async fn load(connection: &mut PgConnection) -> anyhow::Result<AssignmentRules> {
+        let rules = DBAssignmentRule::get_all(connection)
+            .await?
+            .into_iter()
+            .map(AssignmentRule::new)
+            .collect::<Result<Vec<AssignmentRule>, regex::Error>>()?;
+        let default_project_id = DBProject::get(connection).await?.into_iter().find(|p| p.default).and_then(|p| p.id);
+        Ok(AssignmentRules::new(rules, default_project_id))
+    }
+
+    fn new(mut rules: Vec<AssignmentRule>, default_project_id: Option<i64>) -> AssignmentRules {
+        rules.sort_by_key(|rule| (rule.rule.priority, rule.rule.id));
+        AssignmentRules { rules, default_project_id }
+    }
+
+    /// Cost centre and project are taken from the first matching rule setting them, independently of each other
+    pub(crate) fn assign(&self, vendor: &str, item: &mut DBInvoiceItem) {
+        let matching = self.rules.iter().filter(|rule| rule.matches_item(vendor, item)).collect::<Vec<&AssignmentRule>>();
+        item.cost_centre_id = item.cost_centre_id.or_else(|| matching.iter().find_map(|rule| rule.rule.cost_centre_id));
+        item.project_id = item
+            .project_id
+            .or_else(|| matching.iter().find_map(|rule| rule.rule.project_id))
+            .or(self.default_project_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn rule(id: i64, priority: i64) -> DBAssignmentRule {
+        DBAssignmentRule {
+            id: Some(id),
+            name: format!("Regel {}", id),
+            priority,
+            vendor: None,
+            article_number: None,
+            description_regex: None,
+            vat: None,
+            price_min: None,
+            price_max: None,
+            cost_centre_id: None,
+            cost_centre: None,
+            project_id: None,
+            project: None,
+        }
+    }
+
+    fn rules(rules: Vec<DBAssignmentRule>, default_project_id: Option<i64>) -> AssignmentRules {
+        AssignmentRules::new(rules.into_iter().map(|rule| AssignmentRule::new(rule).unwrap()).collect(), default_project_id)
+    }
+
+    fn item(description: &str, article_number: Option<&str>, net_price_single: &str) -> DBInvoiceItem {
+        DBInvoiceItem {
+            id: None,
+            position: 1,
+            invoice_id: 1,
+            typ: "Expense".to_string(),
+            description: description.to_string(),
+            amount: BigDecimal::from(1),
+            net_price_single: BigDecimal::from_str(net_price_single).unwrap(),
+            vat: BigDecimal::from_str("0.19").unwrap(),
+            vat_exempt: false,
+            cost_centre_id: None,
+            cost_centre: None,
+            project_id: None,
+            article_number: article_number.map(str::to_string),
+            ean: None,
+            packaging_unit_amount: None,
+            original_description: None,
+            original_amount: None,
+            original_net_price_single: None,
+            original_vat: None,
+        }
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        let rule = AssignmentRule::new(DBAssignmentRule {
+            vendor: Some("Metro".to_string()),
+            description_regex: Some("^kabel".to_string()),
+            ..rule(1, 1)
+        })
+        .unwrap();
+        assert!(rule.matches_item(" METRO ", &item("Kabel CAT6", None, "1")));
+        assert!(rule.matches_item("metro", &item("KABELTROMMEL", None, "1")));
+        assert!(!rule.matches_item("Reichelt", &item("Kabel CAT6", None, "1")));
+        assert!(!rule.matches_item("Metro", &item("Netzwerkkabel", None, "1")));
+    }
+
+    #[test]
+    fn matching_needs_every_condition() {
+        let rule = AssignmentRule::new(DBAssignmentRule {
+            article_number: Some("4711".to_string()),
+            vat: Some(BigDecimal::from_str("0.19").unwrap()),
+            price_min: Some(BigDecimal::from(1)),
+            price_max: Some(BigDecimal::from(10)),
+            ..rule(1, 1)
+        })
+        .unwrap();
+        assert!(rule.matches_item("Metro", &item("Brot", Some(" 4711 "), "10")));
+        assert!(!rule.matches_item("Metro", &item("Brot", Some("4712"), "10")));
+        assert!(!rule.matches_item("Metro", &item("Brot", None, "10")));
+        assert!(!rule.matches_item("Metro", &item("Brot", Some("4711"), "10.01")));
+        assert!(!rule.matches_item("Metro", &item("Brot", Some("4711"), "0.99")));
+    }
+
+    #[test]
+    fn assign_follows_priority() {
+        let rules = rules(
+            vec![
+                DBAssignmentRule {
+                    cost_centre_id: Some(2),
+                    ..rule(1, 20)
+                },
+                DBAssignmentRule {
+                    cost_centre_id: Some(1),
+                    ..rule(2, 10)
+                },
+                DBAssignmentRule {
+                    project_id: Some(5),
+                    ..rule(3, 30)
+                },
+            ],
+            None,
+        );
+        let mut ii = item("Brot", None, "1");
+        rules.assign("Metro", &mut ii);
+        // Cost centre and project come from different rules, each from the first one setting it
+        assert_eq!(ii.cost_centre_id, Some(1));
+        assert_eq!(ii.project_id, Some(5));
+    }
+
+    #[test]
+    fn default_project_comes_last() {
+        let rules = rules(
+            vec![DBAssignmentRule {
+                description_regex: Some("kabel".to_string()),
+                project_id: Some(5),
+                ..rule(1, 10)
+            }],
+            Some(9),
+        );
+        let mut cable = item("Kabel", None, "1");
+        rules.assign("Metro", &mut cable);
+        assert_eq!(cable.project_id, Some(5));
+
+        let mut bread = item("Brot", None, "1");
+        rules.assign("Metro", &mut bread);
+        assert_eq!(bread.project_id, Some(9));
+        assert_eq!(bread.cost_centre_id, None);
+    }
+
+    #[test]
+    fn assign_keeps_existing_assignments() {
+        let rules = rules(
+            vec![DBAssignmentRule {
+                cost_centre_id: Some(1),
+                project_id: Some(5),
+                ..rule(1, 10)
+            }],
+            Some(9),
+        );
+        let mut ii = DBInvoiceItem {
+            cost_centre_id: Some(3),
+            project_id: Some(7),
+            ..item("Brot", None, "1")
+        };
+        rules.assign("Metro", &mut ii);
+        assert_eq!((ii.cost_centre_id, ii.project_id), (Some(3), Some(7)));
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use sqlx::PgConnection;
+
+use crate::db::invoices::DBInvoiceItem;
+use crate::db::projects::DBProject;
+use crate::db::util::DBResult;
+
+/// Items less similar than this don't count towards a suggestion
+const MIN_SIMILARITY: f64 = 0.3;
+/// Suggestions with a lower confidence (in percent) aren't shown
+const MIN_CONFIDENCE: u32 = 30;
+/// Only the most recent candidates count, so loading the history doesn't grow with the whole database
+const HISTORY_LIMIT: i64 = 2000;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Suggestion {
+    pub id: i64,
+    pub name: String,
+    /// In percent
+    pub confidence: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ItemSuggestions {
+    pub cost_centre: Option<Suggestion>,
+    pub project: Option<Suggestion>,
+}
+
+impl ItemSuggestions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cost_centre.is_none() && self.project.is_none()
+    }
+}
+
+struct HistoryItem {
+    id: i64,
+    vendor: String,
+    article_number: Option<String>,
+    words: HashSet<String>,
+    cost_centre_id: Option<i64>,
+    project_id: Option<i64>,
+}
+
+/// Cost centres and projects previously assigned to items, to suggest them for similar ones
+pub(crate) struct AssignmentHistory {
+    items: Vec<HistoryItem>,
+    cost_centre_names: HashMap<i64, String>,
+    project_names: HashMap<i64, String>,
+}
+
+fn words(description: &str) -> HashSet<String> {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn normalize_article_number(article_number: Option<&str>) -> Option<String> {
+    article_number.map(|a| a.trim().to_lowercase()).filter(|a| !a.is_empty())
+}
+
+impl AssignmentHistory {
+    /// Candidates for the items of an invoice are assigned items from the same vendor or with one of the items' article numbers
+    pub(crate) async fn load(vendor: &str, invoice_items: &[DBInvoiceItem], connection: &mut PgConnection) -> DBResult<AssignmentHistory> {
+        // Inactive projects can't be chosen anymore, so they aren't suggested either
+        let project_names: HashMap<i64, String> = DBProject::get(connection)
+            .await?
+            .into_iter()
+            .filter(|p| p.active)
+            .filter_map(|p| Some((p.id?, p.name)))
+            .collect();
+        let article_numbers: Vec<String> = invoice_items.iter().filter_map(|ii| normalize_article_number(ii.article_number.as_deref())).collect();
+        let mut cost_centre_names: HashMap<i64, String> = HashMap::new();
+        let items = sqlx::query!(
+            r#"SELECT
+                invoice_item.id,
+                invoice.vendor,
+                invoice_item.article_number,
+                invoice_item.description,
+                invoice_item.cost_centre_id,
+                cost_centre.name AS "cost_centre?",
+                invoice_item.project_id
+            FROM invoice_item
+            JOIN invoice ON invoice_item.invoice_id = invoice.id
+            LEFT OUTER JOIN cost_centre ON invoice_item.cost_centre_id = cost_centre.id
+            WHERE invoice.deleted_at IS NULL
+                AND (invoice_item.cost_centre_id IS NOT NULL OR invoice_item.project_id IS NOT NULL)
+                AND (LOWER(TRIM(invoice.vendor)) = LOWER(TRIM($1)) OR LOWER(TRIM(invoice_item.article_number)) = ANY($2))
+            ORDER BY invoice.date DESC, invoice_item.id DESC
+            LIMIT $3"#,
+            vendor,
+            &article_numbers,
+            HISTORY_LIMIT,
+        )
+        .fetch_all(connection)
+        .await?
+        .into_iter()
+        .map(|ii| {
+            if let (Some(id), Some(name)) = (ii.cost_centre_id, &ii.cost_centre) {
+                cost_centre_names.insert(id, name.clone());
+            }
+            HistoryItem {
+                id: ii.id,
+                vendor: ii.vendor.trim().to_lowercase(),
+                article_number: normalize_article_number(ii.article_number.as_deref()),
+                words: words(&ii.description),
+                cost_centre_id: ii.cost_centre_id,
+                project_id: ii.project_id.filter(|id| project_names.contains_key(id)),
+            }
+        })
+        .collect();
+        Ok(AssignmentHistory {
+            items,
+            cost_centre_names,
+            project_names,
+        })
+    }
+
+    /// Similarity between 0 and 1: the same article number counts most, then shared words of the description, both more from the same vendor
+    fn similarity(vendor: &str, article_number: Option<&String>, words: &HashSet<String>, other: &HistoryItem) -> f64 {
+        let same_vendor = vendor == other.vendor;
+        let article = match (article_number, &other.article_number) {
+            (Some(a), Some(b)) if a == b && same_vendor => 1.0,
+            (Some(a), Some(b)) if a == b => 0.7,
+            _ => 0.0,
+        };
+        let shared = words.intersection(&other.words).count();
+        let all = words.union(&other.words).count();
+        let description = match all {
+            0 => 0.0,
+            _ => shared as f64 / all as f64 * if same_vendor { 0.9 } else { 0.7 },
+        };
+        f64::max(article, description)
+    }
+
+    /// Weighs the similar items' choices, the confidence is the winner's share of the votes times its best similarity
+    fn vote(votes: impl Iterator<Item = (i64, f64)>, names: &HashMap<i64, String>) -> Option<Suggestion> {
+        let mut weights: HashMap<i64, (f64, f64)> = HashMap::new();
+        let mut total = 0.0;
+        for (id, similarity) in votes {
+            let (sum, best) = weights.entry(id).or_insert((0.0, 0.0));
+            *sum += similarity;
+            *best = f64::max(*best, similarity);
+            total += similarity;
+        }
+        let (id, (sum, best)) = weights.into_iter().max_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))?;
+        let confidence = (sum / total * best * 100.0).round() as u32;
+        Some(Suggestion {
+            id,
+            name: names.get(&id)?.clone(),
+            confidence,
+        })
+        .filter(|suggestion| suggestion.confidence >= MIN_CONFIDENCE)
+    }
+
+    /// Suggestions for the cost centre and project an item doesn't have yet
+    pub(crate) fn suggest(&self, vendor: &str, item: &DBInvoiceItem) -> ItemSuggestions {
+        let vendor = vendor.trim().to_lowercase();
+        let article_number = normalize_article_number(item.article_number.as_deref());
+        let words = words(&item.description);
+        let similar: Vec<(&HistoryItem, f64)> = self
+            .items
+            .iter()
+            .filter(|other| Some(other.id) != item.id)
+            .map(|other| (other, Self::similarity(&vendor, article_number.as_ref(), &words, other)))
+            .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+            .collect();
+        ItemSuggestions {
+            cost_centre: match item.cost_centre_id {
+                None => Self::vote(similar.iter().filter_map(|(other, s)| Some((other.cost_centre_id?, *s))), &self.cost_centre_names),
+                Some(_) => None,
+            },
+            project: match item.project_id {
+                None => Self::vote(similar.iter().filter_map(|(other, s)| Some((other.project_id?, *s))), &self.project_names),
+                Some(_) => None,
+            },
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::{Date, Duration, Month};
+
+use crate::db::util::DBResult;
+
+/// Restricts the items counted in a summary, all conditions set have to hold
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SummaryFilter {
+    /// Invoice date, both ends included
+    pub from: Option<Date>,
+    pub until: Option<Date>,
+    /// Compared case-insensitively
+    pub vendor: Option<String>,
+    pub project_id: Option<i64>,
+    /// Includes the cost centres below it
+    pub cost_centre_id: Option<i64>,
+    pub vat_exempt: Option<bool>,
+}
+
+/// Length of the periods of a time series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Interval {
+    #[default]
+    Month,
+    /// Weeks start on Monday
+    Week,
+}
+
+impl Interval {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Interval::Month => "Monat",
+            Interval::Week => "Woche",
+        }
+    }
+
+    /// Start of the period following the one starting at `start`
+    pub(crate) fn next(&self, start: Date) -> Date {
+        match self {
+            Interval::Month => match start.month() {
+                Month::December => Date::from_calendar_date(start.year() + 1, Month::January, 1),
+                month => Date::from_calendar_date(start.year(), month.next(), 1),
+            }
+            .unwrap_or(Date::MAX),
+            Interval::Week => start.saturating_add(Duration::weeks(1)),
+        }
+    }
+
+    /// e.g. `03/2026` or `KW 10/2026`
+    pub(crate) fn period_label(&self, start: Date) -> String {
+        match self {
+            Interval::Month => format!("{:02}/{}", u8::from(start.month()), start.year()),
+            Interval::Week => {
+                let (year, week, _) = start.to_iso_week_date();
+                format!("KW {}/{}", week, year)
+            }
+        }
+    }
+}
+
+/// What the spending of a time series is split by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SeriesSplit {
+    /// The cost centre items are assigned to directly, sub-cost centres aren't rolled up
+    #[default]
+    CostCentre,
+    Project,
+    Vendor,
+}
+
+impl SeriesSplit {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            SeriesSplit::CostCentre => "Kostenstelle",
+            SeriesSplit::Project => "Projekt",
+            SeriesSplit::Vendor => "Händler",
+        }
+    }
+}
+
+/// Spending of one group within one period of a time series
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SpendingPoint {
+    /// First day of the period
+    pub period: Date,
+    /// Id of the cost centre or project, or the vendor; `None` for items without cost centre or project
+    pub group: Option<String>,
+    pub sum_net: BigDecimal,
+    pub sum_gross: BigDecimal,
+}
+
+impl SpendingPoint {
+    /// Only periods and groups with items are returned, ordered by period
+    pub(crate) async fn get_series(interval: Interval, split: SeriesSplit, filter: &SummaryFilter, connection: &mut PgConnection) -> DBResult<Vec<SpendingPoint>> {
+        let interval = match interval {
+            Interval::Month => "month",
+            Interval::Week => "week",
+        };
+        let split = match split {
+            SeriesSplit::CostCentre => "cost_centre",
+            SeriesSplit::Project => "project",
+            SeriesSplit::Vendor => "vendor",
+        };
+        Ok(sqlx::query!(
+            r#"SELECT
+                    DATE_TRUNC($7::TEXT, invoice.date)::DATE AS "period!",
+                    CASE $8::TEXT
+                        WHEN 'cost_centre' THEN invoice_item.cost_centre_id::TEXT
+                        WHEN 'project' THEN invoice_item.project_id::TEXT
+                        ELSE invoice.vendor
+                    END AS "group",
+                    ROUND(SUM(filtered.net), 2) AS sum_net,
+                    ROUND(SUM(filtered.gross), 2) AS sum_gross
+                FROM filtered_invoice_item($1, $2, $3, $4, $5, $6) AS filtered
+                JOIN invoice_item ON invoice_item.id = filtered.id
+                JOIN invoice ON invoice_item.invoice_id = invoice.id
+                GROUP BY 1, 2
+                ORDER BY 1, 2"#,
+            filter.from,
+            filter.until,
+            filter.vendor,
+            filter.project_id,
+            filter.cost_centre_id,
+            filter.vat_exempt,
+            interval,
+            split,
+        )
+        .fetch_all(connection)
+        .await?
+        .into_iter()
+        .map(|x| SpendingPoint {
+            period: x.period,
+            group: x.group,
+            sum_net: x.sum_net.unwrap_or_else(BigDecimal::zero),
+            sum_gross: x.sum_gross.unwrap_or_else(BigDecimal::zero),
+        })
+        .collect())
+    }
+}
@@ -4,10 +4,12 @@ use axum::{
     http::{request::Parts, StatusCode},
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{pool::PoolConnection, postgres::PgPool, Postgres};
+use sqlx::{pool::PoolConnection, postgres::PgPool, PgConnection, Postgres};
 use std::fmt;
 use time::{macros::format_description, PrimitiveDateTime};
 
+use crate::utils::RequestUser;
+
 pub(crate) type DBResult<T, E = sqlx::Error> = std::result::Result<T, E>;
 
 pub(crate) struct DatabaseConnection(pub(crate) PoolConnection<Postgres>);
@@ -21,7 +23,7 @@ pub(crate) struct DbDate {
 impl fmt::Display for DbDate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let format = format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
-        write!(f, "{}", self.datetime.map(|d| d.format(&format).ok()).flatten().unwrap_or("".to_string()))
+        write!(f, "{}", self.datetime.and_then(|d| d.format(&format).ok()).unwrap_or("".to_string()))
     }
 }
 
@@ -39,11 +41,26 @@ where
 {
     type Rejection = (StatusCode, String);
 
-    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let pool = PgPool::from_ref(state);
 
-        let conn = pool.acquire().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let mut conn = pool.acquire().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        // The change log records who made a change, as told by a trusted authenticating reverse proxy.
+        // Pooled connections are reused, so the author is set (or cleared) on every request.
+        let RequestUser(user) = RequestUser::from_parts(parts);
+        set_author(user.as_deref().unwrap_or(""), &mut conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
         Ok(Self(conn))
     }
 }
+
+/// Author recorded in the change log for everything done on this connection, empty for none
+pub(crate) async fn set_author(author: &str, connection: &mut PgConnection) -> DBResult<()> {
+    sqlx::query!(r#"SELECT set_config('berechenbarkeit.author', $1, false)"#, author)
+        .fetch_one(connection)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use askama::Template;
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Form;
+use bigdecimal::{BigDecimal, Zero};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use time::macros::format_description;
+use time::Date;
+
+use crate::db::{
+    budgets::{BudgetStatus, DBBudget},
+    cost_centres::DBCostCentre,
+    projects::DBProject,
+    util::DatabaseConnection,
+};
+use crate::handlers::invoice::parse_form_decimal;
+use crate::utils::make_htmx_redirect;
+use crate::{AppError, HtmlTemplate};
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct BudgetForm {
+    name: String,
+    cost_centre: String,
+    project: String,
+    amount: String,
+    period_start: String,
+    period_end: String,
+}
+
+impl From<&DBBudget> for BudgetForm {
+    fn from(budget: &DBBudget) -> Self {
+        let format = format_description!("[year]-[month]-[day]");
+        let to_string = |date: Option<Date>| date.and_then(|d| d.format(&format).ok()).unwrap_or_default();
+        BudgetForm {
+            name: budget.name.clone(),
+            cost_centre: budget.cost_centre_id.map(|id| id.to_string()).unwrap_or_default(),
+            project: budget.project_id.map(|id| id.to_string()).unwrap_or_default(),
+            amount: budget.amount.normalized().to_string(),
+            period_start: to_string(budget.period_start),
+            period_end: to_string(budget.period_end),
+        }
+    }
+}
+
+impl BudgetForm {
+    fn to_budget(&self, id: Option<i64>) -> Result<DBBudget, Vec<String>> {
+        let mut errors: Vec<String> = vec![];
+        let optional = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        let mut optional_date = |value: &str, label: &str| match optional(value) {
+            None => None,
+            Some(value) => Date::parse(&value, format_description!("[year]-[month]-[day]")).ok().or_else(|| {
+                errors.push(format!("{} ist ungültig", label));
+                None
+            }),
+        };
+        let period_start = optional_date(&self.period_start, "Beginn");
+        let period_end = optional_date(&self.period_end, "Ende");
+
+        let budget = DBBudget {
+            id,
+            name: self.name.trim().to_string(),
+            cost_centre_id: optional(&self.cost_centre).and_then(|id| id.parse().ok()),
+            cost_centre: None,
+            project_id: optional(&self.project).and_then(|id| id.parse().ok()),
+            project: None,
+            amount: parse_form_decimal(&self.amount).filter(|amount| *amount >= BigDecimal::zero()).unwrap_or_else(|| {
+                errors.push("Betrag ist ungültig".to_string());
+                BigDecimal::zero()
+            }),
+            period_start,
+            period_end,
+        };
+        if budget.name.is_empty() {
+            errors.push("Name fehlt".to_string());
+        }
+        if budget.cost_centre_id.is_none() && budget.project_id.is_none() {
+            errors.push("Das Budget braucht eine Kostenstelle oder ein Projekt".to_string());
+        }
+        if let (Some(start), Some(end)) = (budget.period_start, budget.period_end) {
+            if start > end {
+                errors.push("Der Beginn liegt nach dem Ende".to_string());
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(budget),
+            false => Err(errors),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "budgets/list.html")]
+struct BudgetListTemplate {
+    budgets: Vec<BudgetStatus>,
+}
+
+#[derive(Template)]
+#[template(path = "budgets/edit.html")]
+struct BudgetEditTemplate {
+    budget_id: Option<i64>,
+    form: BudgetForm,
+    cost_centres: Vec<DBCostCentre>,
+    projects: Vec<DBProject>,
+    errors: Vec<String>,
+}
+
+async fn edit_template(budget_id: Option<i64>, form: BudgetForm, errors: Vec<String>, connection: &mut PgConnection) -> Result<BudgetEditTemplate, AppError> {
+    let cost_centres = DBCostCentre::get_all(connection).await?;
+    let projects = DBProject::get(connection).await?;
+    Ok(BudgetEditTemplate {
+        budget_id,
+        form,
+        cost_centres,
+        projects,
+        errors,
+    })
+}
+
+pub(crate) async fn list(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
+    let budgets = BudgetStatus::get_all(&mut conn).await?;
+    Ok(HtmlTemplate(BudgetListTemplate { budgets }))
+}
+
+pub(crate) async fn new_budget_page(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
+    Ok(HtmlTemplate(edit_template(None, BudgetForm::default(), vec![], &mut conn).await?))
+}
+
+pub(crate) async fn add(DatabaseConnection(mut conn): DatabaseConnection, Form(form): Form<BudgetForm>) -> Result<Response, AppError> {
+    match form.to_budget(None) {
+        Ok(budget) => {
+            DBBudget::insert(&budget, &mut conn).await?;
+            Ok(Redirect::to("/budgets").into_response())
+        }
+        Err(errors) => Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(edit_template(None, form, errors, &mut conn).await?)).into_response()),
+    }
+}
+
+pub(crate) async fn edit_budget_page(DatabaseConnection(mut conn): DatabaseConnection, Path(budget_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let budget = DBBudget::get_by_id(budget_id, &mut conn).await?;
+    Ok(HtmlTemplate(edit_template(Some(budget_id), BudgetForm::from(&budget), vec![], &mut conn).await?))
+}
+
+pub(crate) async fn update(DatabaseConnection(mut conn): DatabaseConnection, Path(budget_id): Path<i64>, Form(form): Form<BudgetForm>) -> Result<Response, AppError> {
+    match form.to_budget(Some(budget_id)) {
+        Ok(budget) => {
+            DBBudget::update(&budget, &mut conn).await?;
+            Ok(Redirect::to("/budgets").into_response())
+        }
+        Err(errors) => Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            HtmlTemplate(edit_template(Some(budget_id), form, errors, &mut conn).await?),
+        )
+            .into_response()),
+    }
+}
+
+pub(crate) async fn delete(req_headers: HeaderMap, DatabaseConnection(mut conn): DatabaseConnection, Path(budget_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    DBBudget::delete(budget_id, &mut conn).await?;
+    make_htmx_redirect(req_headers, "/budgets")
+}
@@ -1,8 +1,12 @@
 use crate::db::{cost_centres::DBCostCentre, util::DatabaseConnection};
 use crate::{utils::make_htmx_redirect, AppError, HtmlTemplate};
 use askama::Template;
-use axum::{extract::Path, http::HeaderMap, Form};
-use axum_core::response::IntoResponse;
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    Form,
+};
+use axum_core::response::{IntoResponse, Response};
 use serde::Deserialize;
 
 #[derive(Template)]
@@ -11,6 +15,13 @@ struct CostCentreListTemplate {
     cost_centres: Vec<DBCostCentre>,
 }
 
+impl CostCentreListTemplate {
+    /// Possible new parents of a cost centre, anything but itself and the cost centres below it
+    fn parent_options(&self, cost_centre: &DBCostCentre) -> Vec<&DBCostCentre> {
+        self.cost_centres.iter().filter(|cc| !cost_centre.contains(cc)).collect()
+    }
+}
+
 pub(crate) async fn cost_centre_list(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
     let cost_centres = DBCostCentre::get_all(&mut conn).await?;
     Ok(HtmlTemplate(CostCentreListTemplate { cost_centres }))
@@ -19,6 +30,17 @@ pub(crate) async fn cost_centre_list(DatabaseConnection(mut conn): DatabaseConne
 #[derive(Deserialize, Debug)]
 pub(crate) struct CostCentreFormInput {
     name: String,
+    #[serde(default)]
+    parent: String,
+}
+
+impl CostCentreFormInput {
+    fn parent_id(&self) -> Result<Option<i64>, AppError> {
+        match self.parent.trim() {
+            "" => Ok(None),
+            parent => Ok(Some(parent.parse()?)),
+        }
+    }
 }
 
 pub(crate) async fn cost_centre_add(
@@ -26,7 +48,7 @@ pub(crate) async fn cost_centre_add(
     DatabaseConnection(mut conn): DatabaseConnection,
     Form(form): Form<CostCentreFormInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    DBCostCentre::insert(&form.name, &mut conn).await?;
+    DBCostCentre::insert(&form.name, form.parent_id()?, &mut conn).await?;
     make_htmx_redirect(request_headers, "/cost_centres")
 }
 
@@ -35,9 +57,15 @@ pub(crate) async fn update(
     DatabaseConnection(mut conn): DatabaseConnection,
     Path(cost_centre_id): Path<i64>,
     Form(cost_centre_form): Form<CostCentreFormInput>,
-) -> Result<impl IntoResponse, AppError> {
-    DBCostCentre::update(cost_centre_id, &cost_centre_form.name, &mut conn).await?;
-    make_htmx_redirect(request_headers, "/cost_centres")
+) -> Result<Response, AppError> {
+    let parent_id = cost_centre_form.parent_id()?;
+    match DBCostCentre::update(cost_centre_id, &cost_centre_form.name, parent_id, &mut conn).await {
+        Err(sqlx::Error::Database(e)) if e.is_check_violation() => {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, "A cost centre can't be moved below itself").into_response());
+        }
+        result => result?,
+    }
+    Ok(make_htmx_redirect(request_headers, "/cost_centres")?.into_response())
 }
 
 pub(crate) async fn cost_centre_delete(
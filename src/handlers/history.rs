@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use askama::Template;
+use axum::extract::Path;
+use axum::response::IntoResponse;
+use berechenbarkeit_lib::PaymentType;
+use bigdecimal::BigDecimal;
+use sqlx::PgConnection;
+use time::macros::format_description;
+use time::PrimitiveDateTime;
+
+use crate::db::{
+    cost_centres::DBCostCentre,
+    history::DBChange,
+    invoices::{DBInvoice, DBInvoiceItem},
+    projects::DBProject,
+    util::DatabaseConnection,
+};
+use crate::{AppError, HtmlTemplate};
+
+/// The overall history only shows the latest changes
+const HISTORY_LIMIT: i64 = 500;
+
+struct ChangeEntry {
+    change: DBChange,
+    /// What was changed, e.g. the description of an item
+    record: String,
+    old_value: String,
+    new_value: String,
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryTemplate {
+    invoice: Option<DBInvoice>,
+    entries: Vec<ChangeEntry>,
+}
+
+/// Names for the records and ids appearing in a list of changes
+struct Names {
+    records: HashMap<(String, i64), String>,
+    cost_centres: HashMap<i64, String>,
+    projects: HashMap<i64, String>,
+}
+
+impl Names {
+    async fn load(changes: &[DBChange], connection: &mut PgConnection) -> Result<Names, AppError> {
+        // Deleted records are only known by the name they were logged with
+        let mut records: HashMap<(String, i64), String> = changes
+            .iter()
+            .filter(|change| change.action != "update")
+            .filter_map(|change| Some(((change.table_name.clone(), change.record_id), change.new_value.clone().or(change.old_value.clone())?)))
+            .collect();
+        let cost_centres: HashMap<i64, String> = DBCostCentre::get_all(connection).await?.into_iter().map(|cc| (cc.id, cc.name)).collect();
+        let projects: HashMap<i64, String> = DBProject::get(connection).await?.into_iter().filter_map(|p| Some((p.id?, p.name))).collect();
+        records.extend(cost_centres.iter().map(|(id, name)| (("cost_centre".to_string(), *id), name.clone())));
+        records.extend(projects.iter().map(|(id, name)| (("project".to_string(), *id), name.clone())));
+        Ok(Names { records, cost_centres, projects })
+    }
+
+    fn record(&self, change: &DBChange) -> String {
+        match self.records.get(&(change.table_name.clone(), change.record_id)) {
+            Some(name) => name.clone(),
+            None => format!("#{}", change.record_id),
+        }
+    }
+
+    fn value(&self, field: Option<&str>, value: Option<&str>) -> String {
+        let Some(value) = value else {
+            return "–".to_string();
+        };
+        let name = |names: &HashMap<i64, String>| value.parse().ok().and_then(|id| names.get(&id).cloned()).unwrap_or_else(|| format!("#{}", value));
+        match (field.unwrap_or_default(), value) {
+            ("cost_centre_id" | "parent_id", _) => name(&self.cost_centres),
+            ("project_id", _) => name(&self.projects),
+            // VAT is stored as a fraction
+            ("vat" | "original_vat", _) => BigDecimal::from_str(value).map_or_else(|_| value.to_string(), |vat| format!("{}%", (vat * BigDecimal::from(100)).normalized())),
+            ("payment_type" | "original_payment_type", _) => PaymentType::from_key(value).map_or_else(|| value.to_string(), |p| p.to_string()),
+            ("date" | "original_date" | "deleted_at" | "start" | "end", _) => timestamp(value).unwrap_or_else(|| value.to_string()),
+            (_, "true") => "ja".to_string(),
+            (_, "false") => "nein".to_string(),
+            (_, _) => value.to_string(),
+        }
+    }
+
+    fn entries(&self, changes: Vec<DBChange>) -> Vec<ChangeEntry> {
+        changes
+            .into_iter()
+            .map(|change| ChangeEntry {
+                record: self.record(&change),
+                old_value: self.value(change.field.as_deref(), change.old_value.as_deref()),
+                new_value: self.value(change.field.as_deref(), change.new_value.as_deref()),
+                change,
+            })
+            .collect()
+    }
+}
+
+/// Timestamps are logged in ISO 8601, e.g. `2026-03-01T12:30:00.123456`
+fn timestamp(value: &str) -> Option<String> {
+    let datetime = PrimitiveDateTime::parse(value, format_description!("[year]-[month]-[day]T[hour]:[minute]:[second][optional [.[subsecond]]]")).ok()?;
+    datetime.format(format_description!("[year]-[month]-[day] [hour]:[minute]:[second]")).ok()
+}
+
+pub(crate) async fn invoice_history(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let invoice = DBInvoice::get_by_id(invoice_id, &mut conn).await?;
+    let changes = DBChange::get_by_invoice_id(invoice_id, &mut conn).await?;
+    let mut names = Names::load(&changes, &mut conn).await?;
+    for ii in DBInvoiceItem::get_by_invoice_id(invoice_id, &mut conn).await? {
+        names.records.insert(("invoice_item".to_string(), ii.id.unwrap()), ii.description);
+    }
+    names.records.insert(("invoice".to_string(), invoice_id), invoice.invoice_number.clone());
+    Ok(HtmlTemplate(HistoryTemplate {
+        invoice: Some(invoice),
+        entries: names.entries(changes),
+    }))
+}
+
+pub(crate) async fn history(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
+    let changes = DBChange::get_latest(HISTORY_LIMIT, &mut conn).await?;
+    let names = Names::load(&changes, &mut conn).await?;
+    Ok(HtmlTemplate(HistoryTemplate {
+        invoice: None,
+        entries: names.entries(changes),
+    }))
+}
@@ -1,23 +1,37 @@
 use crate::db::{
+    budgets::BudgetStatus,
     cost_centres::DBCostCentre,
-    invoices::{DBInvoice, DBInvoiceItem},
+    invoices::{DBInvoice, DBInvoiceItem, DBInvoiceVatCheck},
     projects::DBProject,
+    rules::AssignmentRules,
+    suggestions::{AssignmentHistory, ItemSuggestions},
     util::DatabaseConnection,
 };
+use crate::utils::{make_htmx_redirect, AdminUsers, RequestUser};
 use crate::{AppError, HtmlTemplate};
+use anyhow::anyhow;
 use askama::Template;
 use axum::body::Bytes;
 use axum::extract::{Path, RawForm};
 use axum::http::{HeaderMap, StatusCode};
-use axum::response::{IntoResponse, Redirect};
-use axum::Json;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
 use axum_typed_multipart::{TryFromMultipart, TypedMultipart};
-use berechenbarkeit_lib::{get_parser_for_vendor, InvoiceItemType, InvoiceParser, InvoiceVendor, Vendor};
-use serde::Serialize;
-use std::collections::HashSet;
+use berechenbarkeit_lib::validation::ValidationReport;
+use berechenbarkeit_lib::vendors::xml::{is_xml, parse_xml};
+use berechenbarkeit_lib::{parse_pdf, Invoice, InvoiceItem, InvoiceItemType, InvoiceMeta, InvoiceParseError, InvoiceVendor, PaymentType};
+use bigdecimal::{BigDecimal, One, Zero};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, PgConnection};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
+use time::macros::format_description;
+use time::{Date, PrimitiveDateTime, Time};
 
 #[derive(TryFromMultipart, Debug)]
 pub(crate) struct InvoiceUploadRequest {
@@ -25,20 +39,168 @@ pub(crate) struct InvoiceUploadRequest {
     file: Bytes,
 }
 
-pub(crate) async fn invoice_add_upload(DatabaseConnection(mut conn): DatabaseConnection, TypedMultipart(data): TypedMultipart<InvoiceUploadRequest>) -> Result<Redirect, AppError> {
-    let vendor: Option<InvoiceVendor> = TryInto::<InvoiceVendor>::try_into(data.vendor).ok();
-    let parser: Option<InvoiceParser> = get_parser_for_vendor(vendor.clone());
+#[derive(Template)]
+#[template(path = "invoice/upload_error.html")]
+struct InvoiceUploadErrorTemplate {
+    errors: Vec<String>,
+}
+
+/// XRechnung XML files name their seller themselves, the vendor selection only applies to PDFs
+fn parse_uploaded_invoice(file: &[u8], vendor: String) -> Result<Invoice, InvoiceParseError> {
+    if is_xml(file) {
+        return parse_xml(std::str::from_utf8(file).map_err(|e| InvoiceParseError::InvalidXmlError(e.to_string()))?);
+    }
+    let vendor: Option<InvoiceVendor> = match vendor.as_str() {
+        "auto" => None,
+        _ => Some(InvoiceVendor::try_from(vendor)?),
+    };
+    parse_pdf(file, vendor)
+}
+
+#[derive(Template)]
+#[template(path = "invoice/duplicate.html")]
+struct InvoiceDuplicateTemplate {
+    invoice: DBInvoice,
+    vendor: String,
+    duplicates: Vec<(DBInvoice, Vec<&'static str>)>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct InvoiceUploadPendingForm {
+    file_sha256: String,
+    #[serde(default)]
+    vendor: String,
+}
+
+fn file_sha256(file: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(file))
+}
+
+const PENDING_UPLOAD_PREFIX: &str = "berechenbarkeit-upload-";
+
+/// Pending uploads nobody confirmed or cancelled in this time were abandoned
+const PENDING_UPLOAD_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Uploads which look like duplicates wait here until they're confirmed or cancelled
+fn pending_upload_path(file_sha256: &str) -> Result<PathBuf, AppError> {
+    if file_sha256.len() != 64 || !file_sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid file hash {}", file_sha256).into());
+    }
+    Ok(std::env::temp_dir().join(format!("{}{}", PENDING_UPLOAD_PREFIX, file_sha256)))
+}
 
-    let file = data.file;
+/// Removes the pending uploads older than [`PENDING_UPLOAD_MAX_AGE`] and returns their paths
+pub(crate) fn remove_abandoned_pending_uploads() -> std::io::Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+    for entry in std::fs::read_dir(std::env::temp_dir())? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(PENDING_UPLOAD_PREFIX) {
+            continue;
+        }
+        let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if age > PENDING_UPLOAD_MAX_AGE {
+            std::fs::remove_file(entry.path())?;
+            removed.push(entry.path());
+        }
+    }
+    Ok(removed)
+}
 
-    let parsed_invoice = match parser.unwrap() {
-        InvoiceParser::Regex(p) => p.extract_invoice_data(&file, vendor.unwrap())?,
+async fn upload_invoice(file: &[u8], vendor: String, allow_duplicate: bool, connection: &mut PgConnection) -> Result<Response, AppError> {
+    let parsed_invoice = match parse_uploaded_invoice(file, vendor.clone()) {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            let errors = e.into_list().iter().map(|e| e.to_string()).collect();
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(InvoiceUploadErrorTemplate { errors })).into_response());
+        }
     };
-    let invoice_id = DBInvoice::insert(parsed_invoice.clone().into(), &mut conn).await?;
 
+    if !allow_duplicate {
+        let invoice = DBInvoice {
+            file_sha256: Some(file_sha256(file)),
+            ..parsed_invoice.clone().into()
+        };
+        let duplicates: Vec<(DBInvoice, Vec<&'static str>)> = DBInvoice::find_duplicates(&invoice, connection)
+            .await?
+            .into_iter()
+            .map(|duplicate| {
+                let reasons = duplicate.duplicate_reasons(&invoice);
+                (duplicate, reasons)
+            })
+            .collect();
+        if !duplicates.is_empty() {
+            let mut pending = File::create(pending_upload_path(invoice.file_sha256.as_deref().unwrap_or_default())?)?;
+            pending.write_all(file)?;
+            return Ok((StatusCode::CONFLICT, HtmlTemplate(InvoiceDuplicateTemplate { invoice, vendor, duplicates })).into_response());
+        }
+    }
+
+    let document_type = match is_xml(file) {
+        true => "xml",
+        false => "pdf",
+    };
+    let invoice_id = store_invoice(parsed_invoice, false, Some((file, document_type)), connection).await?;
+
+    Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)).into_response())
+}
+
+pub(crate) async fn invoice_add_upload(DatabaseConnection(mut conn): DatabaseConnection, TypedMultipart(data): TypedMultipart<InvoiceUploadRequest>) -> Result<Response, AppError> {
+    upload_invoice(&data.file, data.vendor, false, &mut conn).await
+}
+
+/// Stores an upload which was held back as a possible duplicate anyway
+pub(crate) async fn invoice_upload_confirm(DatabaseConnection(mut conn): DatabaseConnection, Form(form): Form<InvoiceUploadPendingForm>) -> Result<Response, AppError> {
+    let path = pending_upload_path(&form.file_sha256)?;
+    if !path.exists() {
+        return Ok((StatusCode::NOT_FOUND, "This upload was abandoned and removed, please upload the file again").into_response());
+    }
+    let file = std::fs::read(&path)?;
+    let response = upload_invoice(&file, form.vendor, true, &mut conn).await?;
+    std::fs::remove_file(&path)?;
+    Ok(response)
+}
+
+pub(crate) async fn invoice_upload_cancel(Form(form): Form<InvoiceUploadPendingForm>) -> Result<impl IntoResponse, AppError> {
+    let path = pending_upload_path(&form.file_sha256)?;
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    Ok(Redirect::to("/invoices"))
+}
+
+/// Inserts a parsed or manually entered invoice with its items and keeps its original document, if there is one
+async fn store_invoice(invoice: Invoice, manual: bool, document: Option<(&[u8], &str)>, connection: &mut PgConnection) -> Result<i64, AppError> {
+    // The invoice is saved with everything belonging to it or not at all, a half-saved one would block a re-upload as duplicate
+    let mut tx = connection.begin().await?;
+    for problem in &invoice.validation.problems {
+        tracing::warn!("Invoice {} of {}: {}", invoice.meta.invoice_number, invoice.vendor, problem);
+    }
+    let invoice_id = DBInvoice::insert(
+        DBInvoice {
+            document_type: document.map(|(_, document_type)| document_type.to_string()),
+            manual,
+            file_sha256: document.map(|(file, _)| file_sha256(file)),
+            ..invoice.clone().into()
+        },
+        &mut tx,
+    )
+    .await?;
+    DBInvoiceVatCheck::bulk_insert(
+        &mut tx,
+        invoice
+            .validation
+            .vat_rates
+            .into_iter()
+            .map(|check| DBInvoiceVatCheck::from_check(invoice_id, check))
+            .collect(),
+    )
+    .await?;
+
+    let rules = AssignmentRules::load(&mut tx).await?;
+    let vendor = invoice.vendor.to_string();
     DBInvoiceItem::bulk_insert(
-        &mut conn,
-        (parsed_invoice.items)
+        &mut tx,
+        (invoice.items)
             .into_iter()
             .map(|i| DBInvoiceItem {
                 id: None,
@@ -48,7 +210,11 @@ pub(crate) async fn invoice_add_upload(DatabaseConnection(mut conn): DatabaseCon
                     InvoiceItemType::Credit => "Credit".to_string(),
                     InvoiceItemType::Expense => "Expense".to_string(),
                 },
-                description: i.description.clone(),
+                original_description: Some(i.description.clone()),
+                original_amount: Some(i.amount.clone()),
+                original_net_price_single: Some(i.net_price_single.clone()),
+                original_vat: Some(i.vat.clone()),
+                description: i.description,
                 amount: i.amount,
                 net_price_single: i.net_price_single,
                 vat: i.vat,
@@ -56,19 +222,230 @@ pub(crate) async fn invoice_add_upload(DatabaseConnection(mut conn): DatabaseCon
                 cost_centre_id: None,
                 cost_centre: None,
                 project_id: None,
+                article_number: Some(i.article_number.trim().to_string()).filter(|artnr| !artnr.is_empty()),
+                ean: i.ean,
+                packaging_unit_amount: i.packaging_unit_amount,
+            })
+            .map(|mut ii| {
+                rules.assign(&vendor, &mut ii);
+                ii
             })
             .collect(),
     )
     .await?;
 
-    let file_storage_base_path = std::env::var("BERECHENBARKEIT_STORAGE_BASE_PATH");
-    if file_storage_base_path.is_ok() {
-        let path = format!("{}/invoice-{}.pdf", file_storage_base_path.unwrap(), invoice_id);
+    if let (Some((file, document_type)), Ok(file_storage_base_path)) = (document, std::env::var("BERECHENBARKEIT_STORAGE_BASE_PATH")) {
+        let path = format!("{}/invoice-{}.{}", file_storage_base_path, invoice_id, document_type);
         let mut fileio = File::create(path)?;
-        fileio.write_all(&file)?;
+        fileio.write_all(file)?;
+    }
+    tx.commit().await?;
+
+    Ok(invoice_id)
+}
+
+#[derive(TryFromMultipart, Debug)]
+pub(crate) struct InvoiceManualRequest {
+    vendor: String,
+    invoice_number: String,
+    date: String,
+    sum_gross: String,
+    item_description: Vec<String>,
+    item_amount: Vec<String>,
+    item_price: Vec<String>,
+    item_price_type: Vec<String>,
+    item_vat: Vec<String>,
+    attachment: Option<Bytes>,
+}
+
+/// Line item of the manual entry form as it was entered, so the form can be shown again with errors
+#[derive(Debug, Clone)]
+struct ManualItemInput {
+    description: String,
+    amount: String,
+    price: String,
+    /// `net` or `gross`
+    price_type: String,
+    /// In percent
+    vat: String,
+}
+
+impl ManualItemInput {
+    fn is_empty(&self) -> bool {
+        self.description.trim().is_empty() && self.price.trim().is_empty()
     }
+}
 
-    Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)))
+impl Default for ManualItemInput {
+    fn default() -> Self {
+        ManualItemInput {
+            description: "".to_string(),
+            amount: "1".to_string(),
+            price: "".to_string(),
+            price_type: "gross".to_string(),
+            vat: "19".to_string(),
+        }
+    }
+}
+
+#[derive(Template, Default)]
+#[template(path = "invoice/new.html")]
+struct InvoiceNewTemplate {
+    errors: Vec<String>,
+    vendor: String,
+    invoice_number: String,
+    date: String,
+    sum_gross: String,
+    items: Vec<ManualItemInput>,
+}
+
+pub(crate) async fn invoice_new() -> Result<impl IntoResponse, AppError> {
+    Ok(HtmlTemplate(InvoiceNewTemplate {
+        items: vec![ManualItemInput::default()],
+        ..Default::default()
+    }))
+}
+
+/// Decimal as typed into a form, accepting a decimal comma
+pub(crate) fn parse_form_decimal(value: &str) -> Option<BigDecimal> {
+    BigDecimal::from_str(&value.trim().replace(',', ".")).ok()
+}
+
+/// File type of a receipt attached to a manual invoice, recognised by its first bytes
+fn attachment_type(file: &[u8]) -> Option<&'static str> {
+    if file.starts_with(b"%PDF") {
+        Some("pdf")
+    } else if file.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if file.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else {
+        None
+    }
+}
+
+fn manual_invoice(form: &InvoiceNewTemplate) -> Result<Invoice, Vec<String>> {
+    let mut errors: Vec<String> = vec![];
+    if form.vendor.trim().is_empty() {
+        errors.push("Händler fehlt".to_string());
+    }
+    if form.invoice_number.trim().is_empty() {
+        errors.push("Rechnungsnummer fehlt".to_string());
+    }
+    let date = Date::parse(form.date.trim(), format_description!("[year]-[month]-[day]")).ok();
+    if date.is_none() {
+        errors.push("Rechnungsdatum fehlt oder ist ungültig".to_string());
+    }
+    let sum_gross = parse_form_decimal(&form.sum_gross);
+    if sum_gross.is_none() {
+        errors.push("Summe (Brutto) ist keine Zahl".to_string());
+    }
+
+    let mut items: Vec<InvoiceItem> = vec![];
+    for (i, item) in form.items.iter().enumerate().filter(|(_, item)| !item.is_empty()) {
+        let row = i + 1;
+        if item.description.trim().is_empty() {
+            errors.push(format!("Position {}: Beschreibung fehlt", row));
+        }
+        let amount = parse_form_decimal(&item.amount);
+        if amount.is_none() {
+            errors.push(format!("Position {}: Menge ist keine Zahl", row));
+        }
+        let price = parse_form_decimal(&item.price);
+        if price.is_none() {
+            errors.push(format!("Position {}: Preis ist keine Zahl", row));
+        }
+        let vat = parse_form_decimal(&item.vat).filter(|vat| *vat >= BigDecimal::zero() && *vat <= BigDecimal::from(100));
+        if vat.is_none() {
+            errors.push(format!("Position {}: MwSt muss zwischen 0 und 100 % liegen", row));
+        }
+        let (Some(amount), Some(price), Some(vat)) = (amount, price, vat) else {
+            continue;
+        };
+        let vat = vat / BigDecimal::from(100);
+        let net_price_single = match item.price_type.as_str() {
+            "gross" => (price / (BigDecimal::one() + &vat)).round(4),
+            _ => price,
+        };
+        items.push(InvoiceItem {
+            typ: match (&net_price_single * &amount) >= BigDecimal::zero() {
+                true => InvoiceItemType::Expense,
+                false => InvoiceItemType::Credit,
+            },
+            pos: items.len() as u32 + 1,
+            article_number: "".to_string(),
+            ean: None,
+            description: item.description.trim().to_string(),
+            net_total_price: (&net_price_single * &amount).round(2),
+            net_price_single,
+            vat,
+            amount,
+            packaging_unit_amount: None,
+        });
+    }
+    if form.items.iter().all(ManualItemInput::is_empty) {
+        errors.push("Mindestens eine Position angeben".to_string());
+    }
+
+    match (date, sum_gross, errors.is_empty()) {
+        (Some(date), Some(sum_gross), true) => {
+            let meta = InvoiceMeta {
+                invoice_number: form.invoice_number.trim().to_string(),
+                sum_gross,
+                payment_type: None,
+                date: PrimitiveDateTime::new(date, Time::MIDNIGHT),
+                vat_subtotals: vec![],
+            };
+            Ok(Invoice {
+                vendor: InvoiceVendor::from_seller_name(&form.vendor),
+                validation: ValidationReport::new(&meta, &items),
+                meta,
+                items,
+            })
+        }
+        _ => Err(errors),
+    }
+}
+
+pub(crate) async fn invoice_new_submit(DatabaseConnection(mut conn): DatabaseConnection, TypedMultipart(data): TypedMultipart<InvoiceManualRequest>) -> Result<Response, AppError> {
+    let field = |values: &[String], i: usize| values.get(i).cloned().unwrap_or_default();
+    let form = InvoiceNewTemplate {
+        errors: vec![],
+        vendor: data.vendor,
+        invoice_number: data.invoice_number,
+        date: data.date,
+        sum_gross: data.sum_gross,
+        items: (0..data.item_description.len())
+            .map(|i| ManualItemInput {
+                description: field(&data.item_description, i),
+                amount: field(&data.item_amount, i),
+                price: field(&data.item_price, i),
+                price_type: field(&data.item_price_type, i),
+                vat: field(&data.item_vat, i),
+            })
+            .collect(),
+    };
+
+    // Browsers send an empty file if none was chosen
+    let attachment = data.attachment.filter(|file| !file.is_empty());
+    let mut errors: Vec<String> = vec![];
+    let document_type = attachment.as_deref().and_then(|file| {
+        let document_type = attachment_type(file);
+        if document_type.is_none() {
+            errors.push("Der Beleg muss ein PDF, PNG oder JPEG sein".to_string());
+        }
+        document_type
+    });
+    match manual_invoice(&form) {
+        Ok(invoice) if errors.is_empty() => {
+            let invoice_id = store_invoice(invoice, true, attachment.as_deref().zip(document_type), &mut conn).await?;
+            Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)).into_response())
+        }
+        result => {
+            errors.extend(result.err().unwrap_or_default());
+            Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(InvoiceNewTemplate { errors, ..form })).into_response())
+        }
+    }
 }
 
 #[derive(Template)]
@@ -78,23 +455,95 @@ struct InvoiceEditTemplate {
     invoice_items: Vec<DBInvoiceItem>,
     cost_centres: Vec<DBCostCentre>,
     projects: Vec<DBProject>,
-    diff_invoice_item_sum: f64,
+    diff_invoice_item_sum: BigDecimal,
+    vat_checks: Vec<DBInvoiceVatCheck>,
+    suggestions: HashMap<i64, ItemSuggestions>,
+    /// Exceeded budgets items of this invoice count towards
+    exceeded_budgets: Vec<BudgetStatus>,
+    /// Names of the exceeded budgets per item
+    item_budget_warnings: HashMap<i64, Vec<String>>,
 }
 
-pub(crate) async fn download(Path(invoice_id): Path<i64>) -> Result<(StatusCode, HeaderMap, Vec<u8>), impl IntoResponse> {
-    let file_storage_base_path = std::env::var("BERECHENBARKEIT_STORAGE_BASE_PATH");
-    let mut pdf_content: Vec<u8> = vec![];
-    if file_storage_base_path.is_ok() {
-        let filepath = format!("{}/invoice-{}.pdf", file_storage_base_path.unwrap(), invoice_id);
-        if File::open(filepath).and_then(|mut f| f.read_to_end(&mut pdf_content)).is_err() {
-            return Err((StatusCode::NOT_FOUND, "No invoice could be found"));
-        }
+impl InvoiceEditTemplate {
+    fn suggestion(&self, invoice_item: &DBInvoiceItem) -> Option<&ItemSuggestions> {
+        invoice_item.id.and_then(|id| self.suggestions.get(&id))
     }
+
+    fn budget_warnings(&self, invoice_item: &DBInvoiceItem) -> Option<&Vec<String>> {
+        invoice_item.id.and_then(|id| self.item_budget_warnings.get(&id))
+    }
+
+    fn project_in_range(&self, project: &DBProject) -> bool {
+        project.contains_date(self.invoice.date.date())
+    }
+
+    /// The project of an item whose time range doesn't contain the invoice date
+    fn project_out_of_range(&self, invoice_item: &DBInvoiceItem) -> Option<&DBProject> {
+        self.projects
+            .iter()
+            .find(|p| p.id.is_some() && p.id == invoice_item.project_id)
+            .filter(|p| !self.project_in_range(p))
+    }
+}
+
+/// The stored document of an invoice, not found as well if documents aren't stored at all
+fn read_document(invoice_id: i64, document_type: &str) -> Result<Vec<u8>, (StatusCode, &'static str)> {
+    let not_found = (StatusCode::NOT_FOUND, "No invoice could be found");
+    let file_storage_base_path = std::env::var("BERECHENBARKEIT_STORAGE_BASE_PATH").map_err(|_| not_found)?;
+    let filepath = format!("{}/invoice-{}.{}", file_storage_base_path, invoice_id, document_type);
+    let mut content: Vec<u8> = vec![];
+    File::open(filepath).and_then(|mut f| f.read_to_end(&mut content)).map_err(|_| not_found)?;
+    Ok(content)
+}
+
+fn document_response(invoice_id: i64, document_type: &str, content_type: &str) -> Result<(StatusCode, HeaderMap, Vec<u8>), (StatusCode, &'static str)> {
+    let content = read_document(invoice_id, document_type)?;
     let mut response_headers = HeaderMap::new();
-    response_headers.insert("Content-Disposition", format!("attachment; filename=\"{}.pdf\"", invoice_id).parse().unwrap());
-    response_headers.insert("Content-Length", pdf_content.len().to_string().parse().unwrap());
-    response_headers.insert("Content-Type", "application/pdf".parse().unwrap());
-    Ok((StatusCode::OK, response_headers, pdf_content))
+    response_headers.insert("Content-Disposition", format!("attachment; filename=\"{}.{}\"", invoice_id, document_type).parse().unwrap());
+    response_headers.insert("Content-Length", content.len().to_string().parse().unwrap());
+    response_headers.insert("Content-Type", content_type.parse().unwrap());
+    Ok((StatusCode::OK, response_headers, content))
+}
+
+pub(crate) async fn download(Path(invoice_id): Path<i64>) -> Result<(StatusCode, HeaderMap, Vec<u8>), impl IntoResponse> {
+    document_response(invoice_id, "pdf", "application/pdf")
+}
+
+pub(crate) async fn download_xml(Path(invoice_id): Path<i64>) -> Result<(StatusCode, HeaderMap, Vec<u8>), impl IntoResponse> {
+    document_response(invoice_id, "xml", "application/xml")
+}
+
+/// Original document of an invoice, whatever its type
+pub(crate) async fn download_document(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>) -> Result<Response, AppError> {
+    let invoice = DBInvoice::get_by_id(invoice_id, &mut conn).await?;
+    let Some(document_type) = invoice.document_type else {
+        return Ok((StatusCode::NOT_FOUND, "No invoice could be found").into_response());
+    };
+    let content_type = match document_type.as_str() {
+        "pdf" => "application/pdf",
+        "xml" => "application/xml",
+        "png" => "image/png",
+        "jpg" => "image/jpeg",
+        _ => "application/octet-stream",
+    };
+    Ok(document_response(invoice_id, &document_type, content_type).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "invoice/view.html")]
+struct InvoiceViewTemplate {
+    invoice_id: i64,
+    invoice: Invoice,
+}
+
+/// Human readable rendering of an XRechnung, parsed again from the stored original
+pub(crate) async fn invoice_view(Path(invoice_id): Path<i64>) -> Result<Response, AppError> {
+    let xml = match read_document(invoice_id, "xml") {
+        Ok(xml) => xml,
+        Err(not_found) => return Ok(not_found.into_response()),
+    };
+    let invoice = parse_xml(&String::from_utf8(xml)?)?;
+    Ok(HtmlTemplate(InvoiceViewTemplate { invoice_id, invoice }).into_response())
 }
 
 pub(crate) async fn invoice_edit(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
@@ -102,15 +551,49 @@ pub(crate) async fn invoice_edit(DatabaseConnection(mut conn): DatabaseConnectio
     let invoice_items = DBInvoiceItem::get_by_invoice_id(invoice_id, &mut conn).await?;
     let cost_centres = DBCostCentre::get_all(&mut conn).await?;
     let projects = DBProject::get(&mut conn).await?;
-    let diff_invoice_item_sum = f64::round((invoice.sum_gross - DBInvoiceItem::calculate_sum_gross_by_invoice_id(invoice_id, &mut conn).await?) * 1000f64) / 1000f64;
+    let diff_invoice_item_sum = (&invoice.sum_gross - DBInvoiceItem::calculate_sum_gross_by_invoice_id(invoice_id, &mut conn).await?).round(2);
+    let vat_checks = DBInvoiceVatCheck::get_by_invoice_id(invoice_id, &mut conn).await?;
     let used_project_ids: Vec<_> = invoice_items.clone().into_iter().map(|invoice_item| invoice_item.project_id).collect();
+    let history = AssignmentHistory::load(&invoice.vendor, &invoice_items, &mut conn).await?;
+    let suggestions = invoice_items
+        .iter()
+        .map(|ii| (ii.id.unwrap(), history.suggest(&invoice.vendor, ii)))
+        .filter(|(_, suggestions)| !suggestions.is_empty())
+        .collect();
+
+    // Projects running at the invoice date come first
+    let mut projects: Vec<DBProject> = projects.into_iter().filter(|p| p.active || used_project_ids.contains(&p.id)).collect();
+    projects.sort_by_key(|p| !p.contains_date(invoice.date.date()));
+
+    let mut exceeded_budgets: Vec<BudgetStatus> = vec![];
+    let mut item_budget_warnings: HashMap<i64, Vec<String>> = HashMap::new();
+    for status in BudgetStatus::get_by_invoice_id(invoice_id, &mut conn).await?.into_iter().filter(BudgetStatus::exceeded) {
+        let affected: Vec<i64> = invoice_items
+            .iter()
+            .filter(|ii| {
+                let cost_centre = cost_centres.iter().find(|cc| Some(cc.id) == ii.cost_centre_id);
+                status.budget.applies_to(invoice.date.date(), cost_centre, ii.project_id)
+            })
+            .filter_map(|ii| ii.id)
+            .collect();
+        for id in &affected {
+            item_budget_warnings.entry(*id).or_default().push(status.budget.name.clone());
+        }
+        if !affected.is_empty() {
+            exceeded_budgets.push(status);
+        }
+    }
 
     Ok(HtmlTemplate(InvoiceEditTemplate {
         invoice,
         invoice_items,
         cost_centres,
-        projects: projects.into_iter().filter(|p| p.active || used_project_ids.contains(&p.id)).collect(),
+        projects,
         diff_invoice_item_sum,
+        vat_checks,
+        suggestions,
+        exceeded_budgets,
+        item_budget_warnings,
     }))
 }
 
@@ -126,81 +609,494 @@ pub(crate) async fn invoice_delete_confirm(DatabaseConnection(mut conn): Databas
 }
 
 pub(crate) async fn invoice_delete(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
-    DBInvoice::delete(invoice_id, &mut conn).await?;
+    DBInvoice::trash(invoice_id, &mut conn).await?;
 
     Ok(Redirect::to("/invoices"))
 }
 
-#[derive(Serialize)]
-struct InvoiceItemSplitResponse {
-    new_id: i64,
+#[derive(Template)]
+#[template(path = "invoice/trash.html")]
+struct InvoiceTrashTemplate {
+    invoices: Vec<DBInvoice>,
+    is_admin: bool,
+}
+
+pub(crate) async fn invoice_trash(
+    user: RequestUser,
+    Extension(admins): Extension<AdminUsers>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+) -> Result<impl IntoResponse, AppError> {
+    let invoices = DBInvoice::get_trashed(&mut conn).await?;
+    Ok(HtmlTemplate(InvoiceTrashTemplate {
+        invoices,
+        is_admin: user.is_admin(&admins),
+    }))
 }
 
-pub(crate) async fn invoice_item_split(
+pub(crate) async fn invoice_restore(req_headers: HeaderMap, DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    DBInvoice::restore(invoice_id, &mut conn).await?;
+    make_htmx_redirect(req_headers, &format!("/invoice/{}/edit", invoice_id))
+}
+
+/// Removes a trashed invoice and its stored document for good, only admins may do this
+pub(crate) async fn invoice_purge(
+    req_headers: HeaderMap,
+    user: RequestUser,
+    Extension(admins): Extension<AdminUsers>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Path(invoice_id): Path<i64>,
+) -> Result<Response, AppError> {
+    if !user.is_admin(&admins) {
+        return Ok((StatusCode::FORBIDDEN, "Only admins may purge invoices").into_response());
+    }
+    let invoice = DBInvoice::get_by_id(invoice_id, &mut conn).await?;
+    if invoice.deleted_at.is_none() {
+        return Ok((StatusCode::CONFLICT, "Only invoices in the trash can be purged").into_response());
+    }
+    DBInvoice::delete(invoice_id, &mut conn).await?;
+    if let (Some(document_type), Ok(file_storage_base_path)) = (&invoice.document_type, std::env::var("BERECHENBARKEIT_STORAGE_BASE_PATH")) {
+        let filepath = format!("{}/invoice-{}.{}", file_storage_base_path, invoice_id, document_type);
+        if let Err(e) = std::fs::remove_file(&filepath) {
+            tracing::warn!("Could not remove {}: {}", filepath, e);
+        }
+    }
+    Ok(make_htmx_redirect(req_headers, "/invoices/trash")?.into_response())
+}
+
+/// How the parts of a split are given
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SplitMode {
+    Quantity,
+    Percentage,
+    NetAmount,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct InvoiceItemSplitForm {
+    mode: SplitMode,
+    #[serde(default)]
+    value: Vec<String>,
+    #[serde(default)]
+    cost_centre: Vec<String>,
+    #[serde(default)]
+    project: Vec<String>,
+}
+
+/// One part of a split as entered, so the dialog can be shown again with errors
+#[derive(Debug, Clone, Default)]
+struct SplitPartInput {
+    value: String,
+    cost_centre_id: Option<i64>,
+    project_id: Option<i64>,
+}
+
+#[derive(Template)]
+#[template(path = "invoice/split.html")]
+struct InvoiceItemSplitTemplate {
+    invoice_item: DBInvoiceItem,
+    cost_centres: Vec<DBCostCentre>,
+    projects: Vec<DBProject>,
+    mode: SplitMode,
+    parts: Vec<SplitPartInput>,
+    errors: Vec<String>,
+}
+
+/// Quantities are rounded to this many decimal places when calculated from percentages or net amounts
+const SPLIT_QUANTITY_SCALE: i64 = 4;
+
+async fn split_template(
+    invoice_item: DBInvoiceItem,
+    mode: SplitMode,
+    parts: Vec<SplitPartInput>,
+    errors: Vec<String>,
+    connection: &mut PgConnection,
+) -> Result<InvoiceItemSplitTemplate, AppError> {
+    let cost_centres = DBCostCentre::get_all(connection).await?;
+    let projects = DBProject::get(connection).await?;
+    Ok(InvoiceItemSplitTemplate {
+        projects: projects.into_iter().filter(|p| p.active || (p.id.is_some() && p.id == invoice_item.project_id)).collect(),
+        invoice_item,
+        cost_centres,
+        mode,
+        parts,
+        errors,
+    })
+}
+
+pub(crate) async fn invoice_item_split_page(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Path((_invoice_id, invoiceitem_id)): Path<(i64, i64)>,
+    Path((invoice_id, invoiceitem_id)): Path<(i64, i64)>,
 ) -> Result<impl IntoResponse, AppError> {
     let invoice_item = DBInvoiceItem::get_by_id(invoiceitem_id, &mut conn).await?;
-    let new_id = DBInvoiceItem::insert(
-        DBInvoiceItem {
-            id: None,
-            position: invoice_item.position,
-            invoice_id: invoice_item.invoice_id,
-            typ: invoice_item.typ,
-            description: invoice_item.description,
-            amount: 0f64,
-            net_price_single: invoice_item.net_price_single,
-            vat: invoice_item.vat,
-            vat_exempt: false,
-            cost_centre_id: None,
-            cost_centre: None,
-            project_id: None,
-        },
-        &mut conn,
-    )
-    .await?;
-    Ok(Json(InvoiceItemSplitResponse { new_id }))
+    if invoice_item.invoice_id != invoice_id {
+        return Err(anyhow!("Invoice item {} doesn't belong to invoice {}", invoiceitem_id, invoice_id).into());
+    }
+    let first_part = SplitPartInput {
+        value: "".to_string(),
+        cost_centre_id: invoice_item.cost_centre_id,
+        project_id: invoice_item.project_id,
+    };
+    let template = split_template(invoice_item, SplitMode::Quantity, vec![first_part, SplitPartInput::default()], vec![], &mut conn).await?;
+    Ok(HtmlTemplate(template))
+}
+
+/// Values of the parts as entered, none may be zero and each has the item's sign, except percentages which are always positive
+fn split_values(invoice_item: &DBInvoiceItem, mode: SplitMode, parts: &[SplitPartInput]) -> Result<Vec<BigDecimal>, Vec<String>> {
+    // Parts of a credit are negative as well: quantities like the item's quantity, net amounts like its net total,
+    // which is negative for credits with a negative price and a positive quantity
+    let negative = match mode {
+        SplitMode::NetAmount => &invoice_item.amount * &invoice_item.net_price_single < BigDecimal::zero(),
+        _ => invoice_item.amount < BigDecimal::zero(),
+    };
+    let mut values: Vec<BigDecimal> = vec![];
+    let mut errors: Vec<String> = vec![];
+    for (i, part) in parts.iter().enumerate() {
+        match parse_form_decimal(&part.value) {
+            Some(value) if mode == SplitMode::Percentage && value > BigDecimal::zero() => values.push(value),
+            Some(_) if mode == SplitMode::Percentage => errors.push(format!("Teil {}: Prozentsatz muss größer als 0 sein", i + 1)),
+            Some(value) if !value.is_zero() && (value < BigDecimal::zero()) == negative => values.push(value),
+            Some(_) => errors.push(format!("Teil {}: Wert muss von 0 verschieden sein und das Vorzeichen der Position haben", i + 1)),
+            None => errors.push(format!("Teil {}: Wert ist keine Zahl", i + 1)),
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(values),
+        false => Err(errors),
+    }
+}
+
+/// Quantities of the parts, the last part takes whatever rounding left over so they always add up to `amount`
+fn split_quantities(invoice_item: &DBInvoiceItem, mode: SplitMode, values: &[BigDecimal]) -> Result<Vec<BigDecimal>, String> {
+    let amount = &invoice_item.amount;
+    let (total, unit) = match mode {
+        SplitMode::Quantity => (amount.clone(), "Stück"),
+        SplitMode::Percentage => (BigDecimal::from(100), "%"),
+        SplitMode::NetAmount => ((amount * &invoice_item.net_price_single).round(2), "€"),
+    };
+    let sum: BigDecimal = values.iter().sum();
+    if sum != total {
+        return Err(format!("Die Teile ergeben {} {} statt {} {}", sum, unit, total, unit));
+    }
+    if mode == SplitMode::NetAmount && invoice_item.net_price_single.is_zero() {
+        return Err("Positionen ohne Preis können nur nach Menge aufgeteilt werden".to_string());
+    }
+    let (_, leading) = values.split_last().ok_or("Keine Teile angegeben")?;
+    let mut quantities: Vec<BigDecimal> = leading
+        .iter()
+        .map(|value| match mode {
+            SplitMode::Quantity => value.clone(),
+            SplitMode::Percentage => (amount * value / BigDecimal::from(100)).round(SPLIT_QUANTITY_SCALE),
+            SplitMode::NetAmount => (value / &invoice_item.net_price_single).round(SPLIT_QUANTITY_SCALE),
+        })
+        .collect();
+    quantities.push(amount - quantities.iter().sum::<BigDecimal>());
+    Ok(quantities)
+}
+
+pub(crate) async fn invoice_item_split(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Path((invoice_id, invoiceitem_id)): Path<(i64, i64)>,
+    RawForm(form): RawForm,
+) -> Result<Response, AppError> {
+    let form = serde_html_form::from_bytes::<InvoiceItemSplitForm>(&form)?;
+    // All parts are saved or none, so the invoice never ends up with an item missing or counted twice
+    let mut tx = conn.begin().await?;
+    let invoice = DBInvoice::get_by_id_for_update(invoice_id, &mut tx).await?;
+    if invoice.deleted_at.is_some() {
+        return Ok(trashed_invoice_response(invoice_id));
+    }
+    let invoice_item = DBInvoiceItem::get_by_id(invoiceitem_id, &mut tx).await?;
+    if invoice_item.invoice_id != invoice_id {
+        return Err(anyhow!("Invoice item {} doesn't belong to invoice {}", invoiceitem_id, invoice_id).into());
+    }
+
+    let optional_id = |values: &[String], i: usize| -> Result<Option<i64>, std::num::ParseIntError> { values.get(i).filter(|id| !id.is_empty()).map(|id| id.parse()).transpose() };
+    let parts = (0..form.value.len())
+        .map(|i| {
+            Ok(SplitPartInput {
+                value: form.value[i].clone(),
+                cost_centre_id: optional_id(&form.cost_centre, i)?,
+                project_id: optional_id(&form.project, i)?,
+            })
+        })
+        .collect::<Result<Vec<SplitPartInput>, std::num::ParseIntError>>()?;
+
+    let mut errors: Vec<String> = vec![];
+    if parts.len() < 2 {
+        errors.push("Eine Position muss in mindestens zwei Teile aufgeteilt werden".to_string());
+    }
+    let values = split_values(&invoice_item, form.mode, &parts).map_err(|e| errors.extend(e)).ok();
+    let quantities = match (values, errors.is_empty()) {
+        (Some(values), true) => split_quantities(&invoice_item, form.mode, &values).map_err(|e| errors.push(e)).ok(),
+        _ => None,
+    };
+    let Some(quantities) = quantities else {
+        let template = split_template(invoice_item, form.mode, parts, errors, &mut tx).await?;
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(template)).into_response());
+    };
+
+    // Splitting isn't an edit: the parts of an item with its parsed quantity get their own quantity as the original one
+    let amount_edited = invoice_item.amount_edited();
+    let original_amount = |amount: &BigDecimal| match amount_edited {
+        true => invoice_item.original_amount.clone(),
+        false => invoice_item.original_amount.as_ref().map(|_| amount.clone()),
+    };
+
+    let mut parts = parts.into_iter().zip(quantities);
+    // The first part keeps the item itself, every other one becomes a copy of it
+    if let Some((part, amount)) = parts.next() {
+        DBInvoiceItem::update_original_amount(invoiceitem_id, original_amount(&amount), &mut tx).await?;
+        DBInvoiceItem::update(&DBInvoiceItem { amount, ..invoice_item.clone() }, &mut tx).await?;
+        DBInvoiceItem::update_cost_centre(invoiceitem_id, part.cost_centre_id, &mut tx).await?;
+        DBInvoiceItem::update_project(invoiceitem_id, part.project_id, &mut tx).await?;
+    }
+    for (part, amount) in parts {
+        DBInvoiceItem::insert(
+            DBInvoiceItem {
+                id: None,
+                original_amount: original_amount(&amount),
+                amount,
+                cost_centre_id: part.cost_centre_id,
+                cost_centre: None,
+                project_id: part.project_id,
+                ..invoice_item.clone()
+            },
+            &mut tx,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "invoice/edit_error.html")]
+struct InvoiceEditErrorTemplate {
+    invoice_id: i64,
+    errors: Vec<String>,
 }
 
-pub(crate) async fn invoice_edit_submit(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>, RawForm(form): RawForm) -> Result<Redirect, AppError> {
+/// Invoices in the trash have to be restored before they can be changed
+fn trashed_invoice_response(invoice_id: i64) -> Response {
+    let errors = vec!["Die Rechnung liegt im Papierkorb, sie muss vor dem Bearbeiten wiederhergestellt werden".to_string()];
+    (StatusCode::CONFLICT, HtmlTemplate(InvoiceEditErrorTemplate { invoice_id, errors })).into_response()
+}
+
+/// Expense or credit, depending on the sign of the item's net total
+fn item_type(net_price_single: &BigDecimal, amount: &BigDecimal) -> String {
+    match (net_price_single * amount) >= BigDecimal::zero() {
+        true => "Expense".to_string(),
+        false => "Credit".to_string(),
+    }
+}
+
+/// VAT rate entered in percent, as a fraction
+pub(crate) fn parse_form_vat(value: &str) -> Option<BigDecimal> {
+    parse_form_decimal(value)
+        .filter(|vat| *vat >= BigDecimal::zero() && *vat <= BigDecimal::from(100))
+        .map(|vat| vat / BigDecimal::from(100))
+}
+
+pub(crate) async fn invoice_edit_submit(DatabaseConnection(mut conn): DatabaseConnection, Path(invoice_id): Path<i64>, RawForm(form): RawForm) -> Result<Response, AppError> {
     let form_data = serde_html_form::from_bytes::<Vec<(String, String)>>(&form)?;
 
-    let mut vat_exempt_items_changed = HashSet::new();
-
-    for form_field in form_data.into_iter() {
-        // TODO: Use bulk UPDATE
-        let (invoiceitem_id, data_type) = form_field.0.split_once('-').unwrap();
-        let invoiceitem_id = invoiceitem_id.parse()?;
-        if data_type == "amount" {
-            DBInvoiceItem::update_amount(invoiceitem_id, f64::from_str(&form_field.1)?, &mut conn).await?;
-        } else if data_type == "costcentre" {
-            let mut cost_centre_id = None;
-            if !form_field.1.is_empty() {
-                cost_centre_id = Some(form_field.1.parse()?);
+    // Header fields have plain names, item fields are prefixed with the item's id or with `new` for added items
+    let mut header_fields: HashMap<String, String> = HashMap::new();
+    let mut item_fields: HashMap<(i64, String), String> = HashMap::new();
+    let mut new_item_fields: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in form_data {
+        match name.split_once('-') {
+            Some(("new", field)) => new_item_fields.entry(field.to_string()).or_default().push(value),
+            Some((invoiceitem_id, field)) => {
+                item_fields.insert((invoiceitem_id.parse()?, field.to_string()), value);
             }
-            DBInvoiceItem::update_cost_centre(invoiceitem_id, cost_centre_id, &mut conn).await?;
-        } else if data_type == "vatexempt" {
-            if form_field.1 != "on" {
-                continue;
+            None => {
+                header_fields.insert(name, value);
             }
-            vat_exempt_items_changed.insert(invoiceitem_id);
-            DBInvoiceItem::update_vat_exemption(invoiceitem_id, true, &mut conn).await?;
-        } else if data_type == "project" {
-            if !form_field.1.is_empty() {
-                let project_id: i64 = form_field.1.parse()?;
-                DBInvoiceItem::update_project(invoiceitem_id, project_id, &mut conn).await?;
+        }
+    }
+
+    // Everything is saved or nothing, a failing statement mustn't leave a half-saved invoice behind
+    let mut tx = conn.begin().await?;
+    let mut invoice = DBInvoice::get_by_id_for_update(invoice_id, &mut tx).await?;
+    if invoice.deleted_at.is_some() {
+        return Ok(trashed_invoice_response(invoice_id));
+    }
+    let mut errors: Vec<String> = vec![];
+    if let Some(vendor) = header_fields.get("vendor") {
+        match vendor.trim() {
+            "" => errors.push("Händler fehlt".to_string()),
+            vendor => invoice.vendor = vendor.to_string(),
+        }
+    }
+    if let Some(invoice_number) = header_fields.get("invoice_number") {
+        match invoice_number.trim() {
+            "" => errors.push("Rechnungsnummer fehlt".to_string()),
+            invoice_number => invoice.invoice_number = invoice_number.to_string(),
+        }
+    }
+    if let Some(date) = header_fields.get("date") {
+        match Date::parse(date.trim(), format_description!("[year]-[month]-[day]")) {
+            // Keep the time of day the parser may have found
+            Ok(date) => invoice.date = invoice.date.replace_date(date),
+            Err(_) => errors.push("Rechnungsdatum ist ungültig".to_string()),
+        }
+    }
+    if let Some(sum_gross) = header_fields.get("sum_gross") {
+        match parse_form_decimal(sum_gross) {
+            Some(sum_gross) => invoice.sum_gross = sum_gross,
+            None => errors.push("Summe (Brutto) ist keine Zahl".to_string()),
+        }
+    }
+    if let Some(payment_type) = header_fields.get("payment_type") {
+        match (payment_type.as_str(), PaymentType::from_key(payment_type)) {
+            ("", _) => invoice.payment_type = None,
+            (_, Some(payment_type)) => invoice.payment_type = Some(payment_type.key().to_string()),
+            (_, None) => errors.push("Unbekannte Zahlungsart".to_string()),
+        }
+    }
+
+    let invoice_items = DBInvoiceItem::get_by_invoice_id(invoice_id, &mut tx).await?;
+    let next_position = invoice_items.iter().map(|ii| ii.position).max().unwrap_or(0) + 1;
+    let mut deleted_items: Vec<i64> = vec![];
+    let mut updated_items: Vec<DBInvoiceItem> = vec![];
+    for mut ii in invoice_items {
+        let id = ii.id.unwrap();
+        let field = |name: &str| item_fields.get(&(id, name.to_string()));
+        if field("delete").is_some_and(|delete| delete == "on") {
+            deleted_items.push(id);
+            continue;
+        }
+        let (amount_before, net_price_single_before) = (ii.amount.clone(), ii.net_price_single.clone());
+        if let Some(description) = field("description") {
+            match description.trim() {
+                "" => errors.push(format!("Position {}: Beschreibung fehlt", ii.position)),
+                description => ii.description = description.to_string(),
+            }
+        }
+        if let Some(amount) = field("amount") {
+            match parse_form_decimal(amount) {
+                Some(amount) => ii.amount = amount,
+                None => errors.push(format!("Position {}: Menge ist keine Zahl", ii.position)),
+            }
+        }
+        if let Some(net_price_single) = field("netprice") {
+            match parse_form_decimal(net_price_single) {
+                Some(net_price_single) => ii.net_price_single = net_price_single,
+                None => errors.push(format!("Position {}: Einzelpreis ist keine Zahl", ii.position)),
+            }
+        }
+        if let Some(vat) = field("vat") {
+            match parse_form_vat(vat) {
+                Some(vat) => ii.vat = vat,
+                None => errors.push(format!("Position {}: MwSt muss zwischen 0 und 100 % liegen", ii.position)),
             }
         }
+        if let Some(cost_centre_id) = field("costcentre") {
+            ii.cost_centre_id = match cost_centre_id.is_empty() {
+                true => None,
+                false => Some(cost_centre_id.parse()?),
+            };
+        }
+        if let Some(project_id) = field("project").filter(|project_id| !project_id.is_empty()) {
+            ii.project_id = Some(project_id.parse()?);
+        }
+        // As html <input type="checkbox"> only send the value if they're checked, a missing value means unchecked.
+        ii.vat_exempt = field("vatexempt").is_some_and(|vat_exempt| vat_exempt == "on");
+        // Discounts are credits whatever their sign, only recalculate the type of changed items
+        if ii.amount != amount_before || ii.net_price_single != net_price_single_before {
+            ii.typ = item_type(&ii.net_price_single, &ii.amount);
+        }
+        updated_items.push(ii);
     }
 
-    // As html <input type="checkbox"> only send the value if they're checked, we need to set all other values to null.
-    for ii in DBInvoiceItem::get_by_invoice_id(invoice_id, &mut conn).await? {
-        if !vat_exempt_items_changed.contains(&ii.id.unwrap()) {
-            DBInvoiceItem::update_vat_exemption(ii.id.unwrap(), false, &mut conn).await?
+    if header_fields.get("accept_suggestions").is_some_and(|accept| accept == "on") {
+        let history = AssignmentHistory::load(&invoice.vendor, &updated_items, &mut tx).await?;
+        for ii in updated_items.iter_mut() {
+            let suggestions = history.suggest(&invoice.vendor, ii);
+            ii.cost_centre_id = ii.cost_centre_id.or(suggestions.cost_centre.map(|suggestion| suggestion.id));
+            ii.project_id = ii.project_id.or(suggestions.project.map(|suggestion| suggestion.id));
         }
     }
 
-    Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)))
+    let new_field = |name: &str, i: usize| new_item_fields.get(name).and_then(|values| values.get(i)).map_or("", |value| value.as_str());
+    let mut new_items: Vec<DBInvoiceItem> = vec![];
+    for i in 0..new_item_fields.get("description").map_or(0, Vec::len) {
+        let (description, amount, net_price_single, vat) = (new_field("description", i).trim(), new_field("amount", i), new_field("netprice", i), new_field("vat", i));
+        // Rows added but left empty
+        if description.is_empty() && net_price_single.trim().is_empty() {
+            continue;
+        }
+        let label = format!("Neue Position {}", i + 1);
+        if description.is_empty() {
+            errors.push(format!("{}: Beschreibung fehlt", label));
+        }
+        let amount = parse_form_decimal(amount);
+        if amount.is_none() {
+            errors.push(format!("{}: Menge ist keine Zahl", label));
+        }
+        let net_price_single = parse_form_decimal(net_price_single);
+        if net_price_single.is_none() {
+            errors.push(format!("{}: Einzelpreis ist keine Zahl", label));
+        }
+        let vat = parse_form_vat(vat);
+        if vat.is_none() {
+            errors.push(format!("{}: MwSt muss zwischen 0 und 100 % liegen", label));
+        }
+        let (Some(amount), Some(net_price_single), Some(vat)) = (amount, net_price_single, vat) else {
+            continue;
+        };
+        new_items.push(DBInvoiceItem {
+            id: None,
+            position: next_position + new_items.len() as i64,
+            invoice_id,
+            typ: item_type(&net_price_single, &amount),
+            description: description.to_string(),
+            amount,
+            net_price_single,
+            vat,
+            vat_exempt: false,
+            cost_centre_id: None,
+            cost_centre: None,
+            project_id: None,
+            article_number: None,
+            ean: None,
+            packaging_unit_amount: None,
+            original_description: None,
+            original_amount: None,
+            original_net_price_single: None,
+            original_vat: None,
+        });
+    }
+
+    if !errors.is_empty() {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(InvoiceEditErrorTemplate { invoice_id, errors })).into_response());
+    }
+
+    // TODO: Use bulk UPDATE
+    DBInvoice::update(&invoice, &mut tx).await?;
+    for invoiceitem_id in deleted_items {
+        DBInvoiceItem::delete(invoiceitem_id, &mut tx).await?;
+    }
+    for ii in updated_items {
+        DBInvoiceItem::update(&ii, &mut tx).await?;
+        DBInvoiceItem::update_cost_centre(ii.id.unwrap(), ii.cost_centre_id, &mut tx).await?;
+        DBInvoiceItem::update_vat_exemption(ii.id.unwrap(), ii.vat_exempt, &mut tx).await?;
+        if let Some(project_id) = ii.project_id {
+            DBInvoiceItem::update_project(ii.id.unwrap(), Some(project_id), &mut tx).await?;
+        }
+    }
+    for ii in new_items {
+        DBInvoiceItem::insert(ii, &mut tx).await?;
+    }
+    tx.commit().await?;
+
+    // The split buttons submit the form, so changes made before splitting are kept
+    if let Some(invoiceitem_id) = header_fields.get("split") {
+        return Ok(Redirect::to(&format!("/invoice/{}/invoiceitem/{}/split", invoice_id, invoiceitem_id.parse::<i64>()?)).into_response());
+    }
+    Ok(Redirect::to(&format!("/invoice/{}/edit", invoice_id)).into_response())
 }
 
 #[derive(Template)]
@@ -213,3 +1109,99 @@ pub(crate) async fn invoice_list(DatabaseConnection(mut conn): DatabaseConnectio
     let invoices = DBInvoice::get_all(&mut conn).await?;
     Ok(HtmlTemplate(InvoiceListTemplate { invoices }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn decs(values: &[&str]) -> Vec<BigDecimal> {
+        values.iter().map(|value| dec(value)).collect()
+    }
+
+    fn item(amount: &str, net_price_single: &str) -> DBInvoiceItem {
+        DBInvoiceItem {
+            id: Some(1),
+            position: 1,
+            invoice_id: 1,
+            typ: "Expense".to_string(),
+            description: "Kabel".to_string(),
+            amount: dec(amount),
+            net_price_single: dec(net_price_single),
+            vat: dec("0.19"),
+            vat_exempt: false,
+            cost_centre_id: None,
+            cost_centre: None,
+            project_id: None,
+            article_number: None,
+            ean: None,
+            packaging_unit_amount: None,
+            original_description: None,
+            original_amount: None,
+            original_net_price_single: None,
+            original_vat: None,
+        }
+    }
+
+    fn parts(values: &[&str]) -> Vec<SplitPartInput> {
+        values
+            .iter()
+            .map(|value| SplitPartInput {
+                value: value.to_string(),
+                ..SplitPartInput::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_by_quantity() {
+        assert_eq!(split_quantities(&item("5", "2"), SplitMode::Quantity, &decs(&["2", "3"])), Ok(decs(&["2", "3"])));
+        assert!(split_quantities(&item("5", "2"), SplitMode::Quantity, &decs(&["2", "2"])).is_err());
+    }
+
+    #[test]
+    fn split_remainder_goes_to_last_part() {
+        let quantities = split_quantities(&item("3", "1"), SplitMode::Percentage, &decs(&["33.33", "33.33", "33.34"])).unwrap();
+        assert_eq!(quantities, decs(&["0.9999", "0.9999", "1.0002"]));
+        assert_eq!(quantities.iter().sum::<BigDecimal>(), dec("3"));
+
+        let quantities = split_quantities(&item("1", "3"), SplitMode::NetAmount, &decs(&["1", "1", "1"])).unwrap();
+        assert_eq!(quantities, decs(&["0.3333", "0.3333", "0.3334"]));
+    }
+
+    #[test]
+    fn split_non_integer_amounts() {
+        assert_eq!(split_quantities(&item("2.5", "4"), SplitMode::NetAmount, &decs(&["3", "7"])), Ok(decs(&["0.75", "1.75"])));
+        assert_eq!(split_quantities(&item("0.5", "10"), SplitMode::Quantity, &decs(&["0.2", "0.3"])), Ok(decs(&["0.2", "0.3"])));
+    }
+
+    #[test]
+    fn split_by_net_amount_needs_a_price() {
+        assert!(split_quantities(&item("2", "0"), SplitMode::NetAmount, &decs(&["0", "0"])).is_err());
+    }
+
+    #[test]
+    fn split_parts_must_not_be_zero() {
+        assert!(split_values(&item("2", "1"), SplitMode::Quantity, &parts(&["2", "0"])).is_err());
+        assert!(split_values(&item("2", "1"), SplitMode::Percentage, &parts(&["100", "0"])).is_err());
+    }
+
+    #[test]
+    fn split_parts_have_the_sign_of_the_item() {
+        assert!(split_values(&item("2", "1"), SplitMode::Quantity, &parts(&["3", "-1"])).is_err());
+        assert!(split_values(&item("2", "1"), SplitMode::Percentage, &parts(&["120", "-20"])).is_err());
+        // A credit with a negative price: the quantity stays positive, the net amount is negative
+        assert_eq!(split_values(&item("1", "-10"), SplitMode::NetAmount, &parts(&["-4", "-6"])), Ok(decs(&["-4", "-6"])));
+        assert!(split_values(&item("1", "-10"), SplitMode::NetAmount, &parts(&["4", "6"])).is_err());
+        assert_eq!(split_values(&item("-2", "5"), SplitMode::Quantity, &parts(&["-1", "-1"])), Ok(decs(&["-1", "-1"])));
+    }
+
+    #[test]
+    fn split_values_accept_decimal_commas() {
+        assert_eq!(split_values(&item("2", "1"), SplitMode::Quantity, &parts(&["0,5", "1,5"])), Ok(decs(&["0.5", "1.5"])));
+        assert!(split_values(&item("2", "1"), SplitMode::Quantity, &parts(&["eins", "1"])).is_err());
+    }
+}
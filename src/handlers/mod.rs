@@ -1,5 +1,8 @@
+pub mod budgets;
 pub mod cost_centre;
+pub mod history;
 pub mod home;
 pub mod invoice;
 pub mod projects;
+pub mod rules;
 pub mod summary;
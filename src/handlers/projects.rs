@@ -22,6 +22,7 @@ pub(crate) struct ProjectForm {
     default: Option<String>,
     start: Option<String>,
     end: Option<String>,
+    auto_deactivate: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,7 +49,7 @@ struct ProjectNewTemplate {}
 impl From<ProjectForm> for DBProject {
     fn from(e: ProjectForm) -> Self {
         let format = format_description!("[year]-[month]-[day]T[hour]:[minute]");
-        let parse_date_into_option = |i: Option<String>| -> Option<PrimitiveDateTime> { i.as_ref().map(|d| PrimitiveDateTime::parse(d, &format).ok()).flatten() };
+        let parse_date_into_option = |i: Option<String>| -> Option<PrimitiveDateTime> { i.as_ref().and_then(|d| PrimitiveDateTime::parse(d, &format).ok()) };
         let html_checkbox_to_boolean = |c: Option<String>| -> bool { c.is_some() && c.unwrap().as_str() == "true" };
         DBProject {
             id: None,
@@ -62,6 +63,7 @@ impl From<ProjectForm> for DBProject {
             end: DbDate {
                 datetime: parse_date_into_option(e.end),
             },
+            auto_deactivate: html_checkbox_to_boolean(e.auto_deactivate),
         }
     }
 }
@@ -0,0 +1,258 @@
+use askama::Template;
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Form;
+use bigdecimal::BigDecimal;
+use serde::Deserialize;
+use sqlx::PgConnection;
+
+use crate::db::{
+    cost_centres::DBCostCentre,
+    invoices::{DBInvoiceItem, InvoiceItemExtended},
+    projects::DBProject,
+    rules::{AssignmentRule, DBAssignmentRule},
+    summary::SummaryFilter,
+    util::DatabaseConnection,
+};
+use crate::handlers::invoice::{parse_form_decimal, parse_form_vat};
+use crate::utils::make_htmx_redirect;
+use crate::{AppError, HtmlTemplate};
+
+/// A rule as typed into the form, so it can be shown again with errors
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub(crate) struct RuleForm {
+    name: String,
+    priority: String,
+    vendor: String,
+    article_number: String,
+    description_regex: String,
+    vat: String,
+    price_min: String,
+    price_max: String,
+    cost_centre: String,
+    project: String,
+}
+
+impl From<&DBAssignmentRule> for RuleForm {
+    fn from(rule: &DBAssignmentRule) -> Self {
+        let to_string = |value: Option<&BigDecimal>| value.map(|v| v.normalized().to_string()).unwrap_or_default();
+        RuleForm {
+            name: rule.name.clone(),
+            priority: rule.priority.to_string(),
+            vendor: rule.vendor.clone().unwrap_or_default(),
+            article_number: rule.article_number.clone().unwrap_or_default(),
+            description_regex: rule.description_regex.clone().unwrap_or_default(),
+            vat: to_string(rule.vat_percent().as_ref()),
+            price_min: to_string(rule.price_min.as_ref()),
+            price_max: to_string(rule.price_max.as_ref()),
+            cost_centre: rule.cost_centre_id.map(|id| id.to_string()).unwrap_or_default(),
+            project: rule.project_id.map(|id| id.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl RuleForm {
+    fn to_rule(&self, id: Option<i64>) -> Result<DBAssignmentRule, Vec<String>> {
+        let mut errors: Vec<String> = vec![];
+        let optional = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        let mut optional_decimal = |value: &str, label: &str, parse: fn(&str) -> Option<BigDecimal>| match optional(value) {
+            None => None,
+            Some(value) => parse(&value).or_else(|| {
+                errors.push(format!("{} ist ungültig", label));
+                None
+            }),
+        };
+        let vat = optional_decimal(&self.vat, "MwSt", parse_form_vat);
+        let price_min = optional_decimal(&self.price_min, "Mindestpreis", parse_form_decimal);
+        let price_max = optional_decimal(&self.price_max, "Höchstpreis", parse_form_decimal);
+
+        let rule = DBAssignmentRule {
+            id,
+            name: self.name.trim().to_string(),
+            priority: self.priority.trim().parse().unwrap_or_else(|_| {
+                errors.push("Priorität muss eine ganze Zahl sein".to_string());
+                0
+            }),
+            vendor: optional(&self.vendor),
+            article_number: optional(&self.article_number),
+            description_regex: optional(&self.description_regex),
+            vat,
+            price_min,
+            price_max,
+            cost_centre_id: optional(&self.cost_centre).and_then(|id| id.parse().ok()),
+            cost_centre: None,
+            project_id: optional(&self.project).and_then(|id| id.parse().ok()),
+            project: None,
+        };
+        if rule.name.is_empty() {
+            errors.push("Name fehlt".to_string());
+        }
+        if let Err(e) = rule.description_regex() {
+            errors.push(format!("Regulärer Ausdruck ist ungültig: {}", e));
+        }
+        if let (Some(min), Some(max)) = (&rule.price_min, &rule.price_max) {
+            if min > max {
+                errors.push("Mindestpreis ist größer als der Höchstpreis".to_string());
+            }
+        }
+        let has_condition = rule.vendor.is_some()
+            || rule.article_number.is_some()
+            || rule.description_regex.is_some()
+            || rule.vat.is_some()
+            || rule.price_min.is_some()
+            || rule.price_max.is_some();
+        if !has_condition {
+            errors.push("Die Regel braucht mindestens eine Bedingung".to_string());
+        }
+        if rule.cost_centre_id.is_none() && rule.project_id.is_none() {
+            errors.push("Die Regel muss eine Kostenstelle oder ein Projekt zuordnen".to_string());
+        }
+        match errors.is_empty() {
+            true => Ok(rule),
+            false => Err(errors),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "rules/list.html")]
+struct RuleListTemplate {
+    rules: Vec<DBAssignmentRule>,
+    default_project: Option<DBProject>,
+}
+
+impl RuleListTemplate {
+    fn has_broken_rules(&self) -> bool {
+        self.rules.iter().any(DBAssignmentRule::is_broken)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "rules/edit.html")]
+struct RuleEditTemplate {
+    rule_id: Option<i64>,
+    form: RuleForm,
+    cost_centres: Vec<DBCostCentre>,
+    projects: Vec<DBProject>,
+    errors: Vec<String>,
+}
+
+struct RulePreviewItem {
+    item: InvoiceItemExtended,
+    project: Option<String>,
+    assigns_cost_centre: bool,
+    assigns_project: bool,
+}
+
+#[derive(Template)]
+#[template(path = "rules/preview.html")]
+struct RulePreviewTemplate {
+    rule: DBAssignmentRule,
+    items: Vec<RulePreviewItem>,
+    assignable: usize,
+}
+
+async fn edit_template(rule_id: Option<i64>, form: RuleForm, errors: Vec<String>, connection: &mut PgConnection) -> Result<RuleEditTemplate, AppError> {
+    let cost_centres = DBCostCentre::get_all(connection).await?;
+    let selected_project: Option<i64> = form.project.parse().ok();
+    let projects = DBProject::get(connection).await?.into_iter().filter(|p| p.active || p.id == selected_project).collect();
+    Ok(RuleEditTemplate {
+        rule_id,
+        form,
+        cost_centres,
+        projects,
+        errors,
+    })
+}
+
+pub(crate) async fn list(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
+    let rules = DBAssignmentRule::get_all(&mut conn).await?;
+    let default_project = DBProject::get(&mut conn).await?.into_iter().find(|p| p.default);
+    Ok(HtmlTemplate(RuleListTemplate { rules, default_project }))
+}
+
+pub(crate) async fn new_rule_page(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
+    let next_priority = DBAssignmentRule::get_all(&mut conn)
+        .await?
+        .iter()
+        .map(|rule| rule.priority)
+        .max()
+        .map_or(10, |priority| priority + 10);
+    let form = RuleForm {
+        priority: next_priority.to_string(),
+        ..RuleForm::default()
+    };
+    Ok(HtmlTemplate(edit_template(None, form, vec![], &mut conn).await?))
+}
+
+pub(crate) async fn add(DatabaseConnection(mut conn): DatabaseConnection, Form(form): Form<RuleForm>) -> Result<Response, AppError> {
+    match form.to_rule(None) {
+        Ok(rule) => {
+            DBAssignmentRule::insert(&rule, &mut conn).await?;
+            Ok(Redirect::to("/rules").into_response())
+        }
+        Err(errors) => Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(edit_template(None, form, errors, &mut conn).await?)).into_response()),
+    }
+}
+
+pub(crate) async fn edit_rule_page(DatabaseConnection(mut conn): DatabaseConnection, Path(rule_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let rule = DBAssignmentRule::get_by_id(rule_id, &mut conn).await?;
+    Ok(HtmlTemplate(edit_template(Some(rule_id), RuleForm::from(&rule), vec![], &mut conn).await?))
+}
+
+pub(crate) async fn update(DatabaseConnection(mut conn): DatabaseConnection, Path(rule_id): Path<i64>, Form(form): Form<RuleForm>) -> Result<Response, AppError> {
+    match form.to_rule(Some(rule_id)) {
+        Ok(rule) => {
+            DBAssignmentRule::update(&rule, &mut conn).await?;
+            Ok(Redirect::to("/rules").into_response())
+        }
+        Err(errors) => Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(edit_template(Some(rule_id), form, errors, &mut conn).await?)).into_response()),
+    }
+}
+
+pub(crate) async fn delete(req_headers: HeaderMap, DatabaseConnection(mut conn): DatabaseConnection, Path(rule_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    DBAssignmentRule::delete(rule_id, &mut conn).await?;
+    make_htmx_redirect(req_headers, "/rules")
+}
+
+async fn matching_items(rule: &DBAssignmentRule, connection: &mut PgConnection) -> Result<Vec<InvoiceItemExtended>, AppError> {
+    let matcher = AssignmentRule::new(rule.clone())?;
+    Ok(DBInvoiceItem::get_all(&SummaryFilter::default(), connection)
+        .await?
+        .into_iter()
+        .filter(|ii| matcher.matches(&ii.invoice_vendor, ii.article_number.as_deref(), &ii.description, &ii.vat, &ii.net_price_single))
+        .collect())
+}
+
+pub(crate) async fn preview(DatabaseConnection(mut conn): DatabaseConnection, Path(rule_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let rule = DBAssignmentRule::get_by_id(rule_id, &mut conn).await?;
+    let projects = DBProject::get(&mut conn).await?;
+    let items: Vec<RulePreviewItem> = matching_items(&rule, &mut conn)
+        .await?
+        .into_iter()
+        .map(|item| RulePreviewItem {
+            project: projects.iter().find(|p| p.id.is_some() && p.id == item.project_id).map(|p| p.name.clone()),
+            assigns_cost_centre: rule.cost_centre_id.is_some() && item.cost_centre_id.is_none(),
+            assigns_project: rule.project_id.is_some() && item.project_id.is_none(),
+            item,
+        })
+        .collect();
+    let assignable = items.iter().filter(|i| i.assigns_cost_centre || i.assigns_project).count();
+    Ok(HtmlTemplate(RulePreviewTemplate { rule, items, assignable }))
+}
+
+/// Assigns the rule's cost centre and project to matching items which don't have one yet
+pub(crate) async fn apply(DatabaseConnection(mut conn): DatabaseConnection, Path(rule_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let rule = DBAssignmentRule::get_by_id(rule_id, &mut conn).await?;
+    for item in matching_items(&rule, &mut conn).await? {
+        if item.cost_centre_id.is_none() && rule.cost_centre_id.is_some() {
+            DBInvoiceItem::update_cost_centre(item.id, rule.cost_centre_id, &mut conn).await?;
+        }
+        if item.project_id.is_none() && rule.project_id.is_some() {
+            DBInvoiceItem::update_project(item.id, rule.project_id, &mut conn).await?;
+        }
+    }
+    Ok(Redirect::to(&format!("/rules/{}/preview", rule_id)))
+}
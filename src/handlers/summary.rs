@@ -1,65 +1,717 @@
+use std::collections::HashMap;
+
 use crate::db::{
-    cost_centres::{CostCentreWithSum, DBCostCentre},
-    invoices::DBInvoiceItem,
+    cost_centres::{CostCentreSummary, DBCostCentre},
+    invoices::{DBInvoice, DBInvoiceItem},
+    projects::{DBProject, ProjectCostCentreSum},
+    summary::{Interval, SeriesSplit, SpendingPoint, SummaryFilter},
     util::DatabaseConnection,
 };
 use crate::{AppError, HtmlTemplate};
 use askama::Template;
+use axum::extract::{Path, Query};
+use axum::Json;
 use axum_core::response::IntoResponse;
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
 use http::header;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::macros::format_description;
+use time::Date;
+
+/// Filters of the summaries and exports as given in the query string, empty values don't filter
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct SummaryQuery {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    from: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    until: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    vendor: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    project: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    cost_centre: String,
+    /// `exempt` or `taxed`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    tax: String,
+}
+
+/// A filter with everything needed to show it, and to choose another one
+struct ActiveFilter {
+    query: SummaryQuery,
+    filter: SummaryFilter,
+    /// The conditions in words, for the page and the first line of the aggregated and raw exports
+    descriptions: Vec<String>,
+    /// The conditions for the export file name
+    slugs: Vec<String>,
+    vendors: Vec<String>,
+    projects: Vec<DBProject>,
+    cost_centres: Vec<DBCostCentre>,
+}
+
+/// Lower case letters, digits and dashes only, so it fits into a file name
+fn slug(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+impl ActiveFilter {
+    /// Values which can't be parsed or refer to nothing known are ignored
+    async fn load(query: SummaryQuery, connection: &mut PgConnection) -> Result<ActiveFilter, AppError> {
+        let vendors = DBInvoice::get_vendors(connection).await?;
+        let projects = DBProject::get(connection).await?;
+        let cost_centres = DBCostCentre::get_all(connection).await?;
+        let mut descriptions: Vec<String> = vec![];
+        let mut slugs: Vec<String> = vec![];
+
+        let date = |value: &str| Date::parse(value.trim(), format_description!("[year]-[month]-[day]")).ok();
+        let label = |date: Date| date.format(format_description!("[day].[month].[year]")).unwrap_or_default();
+        let (from, until) = (date(&query.from), date(&query.until));
+        match (from, until) {
+            (Some(from), Some(until)) => descriptions.push(format!("Rechnungsdatum {} – {}", label(from), label(until))),
+            (Some(from), None) => descriptions.push(format!("Rechnungsdatum ab {}", label(from))),
+            (None, Some(until)) => descriptions.push(format!("Rechnungsdatum bis {}", label(until))),
+            (None, None) => {}
+        }
+        if from.is_some() || until.is_some() {
+            slugs.push(format!(
+                "{}_{}",
+                from.map(|d| d.to_string()).unwrap_or_default(),
+                until.map(|d| d.to_string()).unwrap_or_default()
+            ));
+        }
+
+        let vendor = Some(query.vendor.trim().to_string()).filter(|vendor| !vendor.is_empty());
+        if let Some(vendor) = &vendor {
+            descriptions.push(format!("Händler {}", vendor));
+            slugs.push(slug(vendor));
+        }
+        let project = projects.iter().find(|p| p.id.is_some() && p.id == query.project.parse().ok());
+        if let Some(project) = project {
+            descriptions.push(format!("Projekt {}", project.name));
+            slugs.push(slug(&project.name));
+        }
+        let cost_centre = cost_centres.iter().find(|cc| Some(cc.id) == query.cost_centre.parse().ok());
+        if let Some(cost_centre) = cost_centre {
+            descriptions.push(format!("Kostenstelle {}", cost_centre.path));
+            slugs.push(slug(&cost_centre.path));
+        }
+        let vat_exempt = match query.tax.as_str() {
+            "exempt" => Some(true),
+            "taxed" => Some(false),
+            _ => None,
+        };
+        match vat_exempt {
+            Some(true) => descriptions.push("nur MwSt-befreite Positionen".to_string()),
+            Some(false) => descriptions.push("nur MwSt-pflichtige Positionen".to_string()),
+            None => {}
+        }
+        if let Some(vat_exempt) = vat_exempt {
+            slugs.push(if vat_exempt { "mwst-befreit" } else { "mwst-pflichtig" }.to_string());
+        }
+
+        let filter = SummaryFilter {
+            from,
+            until,
+            vendor,
+            project_id: project.and_then(|p| p.id),
+            cost_centre_id: cost_centre.map(|cc| cc.id),
+            vat_exempt,
+        };
+        Ok(ActiveFilter {
+            query,
+            filter,
+            descriptions,
+            slugs,
+            vendors,
+            projects,
+            cost_centres,
+        })
+    }
+
+    /// To pass the filter on to other summaries and the exports
+    fn query_string(&self) -> String {
+        match self.descriptions.is_empty() {
+            true => "".to_string(),
+            false => format!("?{}", serde_urlencoded::to_string(&self.query).unwrap_or_default()),
+        }
+    }
+
+    fn filename(&self, name: &str) -> String {
+        let mut parts = vec![format!("berechenbarkeit-{}", name)];
+        parts.extend(self.slugs.iter().cloned());
+        format!("{}.csv", parts.join("_"))
+    }
+
+    /// The aggregated and raw exports start with a line naming the filter, if there is one, so the file still says what it holds once renamed
+    fn csv_writer(&self) -> Result<csv::Writer<Vec<u8>>, AppError> {
+        let mut wtr = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+        if !self.descriptions.is_empty() {
+            wtr.write_record([format!("# Filter: {}", self.descriptions.join(", "))])?;
+        }
+        Ok(wtr)
+    }
+
+    fn csv_response(&self, name: &str, wtr: csv::Writer<Vec<u8>>) -> Result<impl IntoResponse, AppError> {
+        let csv_string = String::from_utf8(wtr.into_inner()?)?;
+        Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", self.filename(name))),
+            ],
+            csv_string,
+        ))
+    }
+}
+
+/// Net, VAT and gross sum of some items
+#[derive(Debug, Clone)]
+struct Sums {
+    net: BigDecimal,
+    vat: BigDecimal,
+    gross: BigDecimal,
+}
+
+impl Default for Sums {
+    fn default() -> Self {
+        let zero = BigDecimal::zero().with_scale(2);
+        Sums {
+            net: zero.clone(),
+            vat: zero.clone(),
+            gross: zero,
+        }
+    }
+}
+
+impl Sums {
+    /// The sums are rounded to cents already, keeping two decimals shows them as such
+    fn add(&mut self, sum: &ProjectCostCentreSum) {
+        self.net = (&self.net + &sum.sum_net).with_scale(2);
+        self.vat = (&self.vat + &sum.sum_vat).with_scale(2);
+        self.gross = (&self.gross + &sum.sum_gross).with_scale(2);
+    }
+}
+
+/// A project or cost centre in the cross-tab, `None` collects the items without one
+struct Heading {
+    id: Option<i64>,
+    name: String,
+}
+
+/// Sums of projects × cost centres with the totals of every row and column
+struct CrossTab {
+    projects: Vec<Heading>,
+    /// Only the cost centres items are assigned to directly, sub-cost centres aren't rolled up
+    cost_centres: Vec<Heading>,
+    cells: HashMap<(Option<i64>, Option<i64>), Sums>,
+    project_totals: HashMap<Option<i64>, Sums>,
+    cost_centre_totals: HashMap<Option<i64>, Sums>,
+    total: Sums,
+}
+
+impl CrossTab {
+    async fn load(filter: &SummaryFilter, connection: &mut PgConnection) -> Result<CrossTab, AppError> {
+        let sums = DBProject::get_summary(filter, connection).await?;
+        let mut cross_tab = CrossTab {
+            projects: vec![],
+            cost_centres: vec![],
+            cells: HashMap::new(),
+            project_totals: HashMap::new(),
+            cost_centre_totals: HashMap::new(),
+            total: Sums::default(),
+        };
+        for sum in &sums {
+            cross_tab.cells.entry((sum.project_id, sum.cost_centre_id)).or_default().add(sum);
+            cross_tab.project_totals.entry(sum.project_id).or_default().add(sum);
+            cross_tab.cost_centre_totals.entry(sum.cost_centre_id).or_default().add(sum);
+            cross_tab.total.add(sum);
+        }
+
+        let projects = DBProject::get(connection).await?.into_iter().map(|p| Heading { id: p.id, name: p.name });
+        let cost_centres = DBCostCentre::get_all(connection).await?.into_iter().map(|cc| Heading { id: Some(cc.id), name: cc.path });
+        let without = |name: &str| Heading { id: None, name: name.to_string() };
+        cross_tab.projects = projects.chain([without("Ohne Projekt")]).filter(|p| cross_tab.project_totals.contains_key(&p.id)).collect();
+        cross_tab.cost_centres = cost_centres
+            .chain([without("Ohne Kostenstelle")])
+            .filter(|cc| cross_tab.cost_centre_totals.contains_key(&cc.id))
+            .collect();
+        Ok(cross_tab)
+    }
+
+    fn cell(&self, project_id: Option<i64>, cost_centre_id: Option<i64>) -> Sums {
+        self.cells.get(&(project_id, cost_centre_id)).cloned().unwrap_or_default()
+    }
+
+    fn project_total(&self, project_id: Option<i64>) -> Sums {
+        self.project_totals.get(&project_id).cloned().unwrap_or_default()
+    }
+
+    fn cost_centre_total(&self, cost_centre_id: Option<i64>) -> Sums {
+        self.cost_centre_totals.get(&cost_centre_id).cloned().unwrap_or_default()
+    }
+}
+
+/// How the time series on the summary page is built
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct SeriesQuery {
+    interval: Interval,
+    split: SeriesSplit,
+}
+
+impl SeriesQuery {
+    /// The filter's query string extended by the series settings
+    fn query_string(&self, filter: &ActiveFilter) -> String {
+        let parts = [serde_urlencoded::to_string(&filter.query), serde_urlencoded::to_string(self)];
+        format!("?{}", parts.into_iter().flatten().filter(|part| !part.is_empty()).collect::<Vec<String>>().join("&"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SeriesPoint {
+    /// First day of the period
+    period: Date,
+    label: String,
+    net: BigDecimal,
+    gross: BigDecimal,
+}
+
+/// Spending of one cost centre, project or vendor over time
+#[derive(Debug, Clone, Serialize)]
+struct Series {
+    /// Id of the cost centre or project, or the vendor; `None` collects the items without one
+    id: Option<String>,
+    name: String,
+    /// One for every period of the time series
+    points: Vec<SeriesPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TimeSeries {
+    interval: Interval,
+    split: SeriesSplit,
+    filter: Vec<String>,
+    /// Ordered by gross spending, the largest first
+    series: Vec<Series>,
+}
+
+impl TimeSeries {
+    async fn load(query: SeriesQuery, filter: &ActiveFilter, connection: &mut PgConnection) -> Result<TimeSeries, AppError> {
+        let points = SpendingPoint::get_series(query.interval, query.split, &filter.filter, connection).await?;
+
+        // Periods without any spending in between are kept as zero
+        let mut periods: Vec<Date> = vec![];
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            let mut period = first.period;
+            while period <= last.period {
+                periods.push(period);
+                period = query.interval.next(period);
+            }
+        }
+
+        let names: HashMap<String, String> = match query.split {
+            SeriesSplit::CostCentre => filter.cost_centres.iter().map(|cc| (cc.id.to_string(), cc.path.clone())).collect(),
+            SeriesSplit::Project => filter.projects.iter().filter_map(|p| Some((p.id?.to_string(), p.name.clone()))).collect(),
+            SeriesSplit::Vendor => HashMap::new(),
+        };
+        let name = |group: &Option<String>| match (group, query.split) {
+            (None, SeriesSplit::CostCentre) => "Ohne Kostenstelle".to_string(),
+            (None, SeriesSplit::Project) => "Ohne Projekt".to_string(),
+            (None, SeriesSplit::Vendor) => "Ohne Händler".to_string(),
+            (Some(group), _) => names.get(group).cloned().unwrap_or_else(|| group.clone()),
+        };
+
+        let mut totals: HashMap<Option<String>, BigDecimal> = HashMap::new();
+        let mut values: HashMap<(Date, Option<String>), &SpendingPoint> = HashMap::new();
+        for point in &points {
+            *totals.entry(point.group.clone()).or_insert_with(BigDecimal::zero) += &point.sum_gross;
+            values.insert((point.period, point.group.clone()), point);
+        }
+        let mut groups: Vec<(Option<String>, BigDecimal)> = totals.into_iter().collect();
+        groups.sort_by(|(a, a_total), (b, b_total)| b_total.cmp(a_total).then_with(|| name(a).cmp(&name(b))));
+
+        let zero = BigDecimal::zero().with_scale(2);
+        let series = groups
+            .into_iter()
+            .map(|(group, _)| Series {
+                points: periods
+                    .iter()
+                    .map(|period| {
+                        let value = values.get(&(*period, group.clone()));
+                        SeriesPoint {
+                            period: *period,
+                            label: query.interval.period_label(*period),
+                            net: value.map_or_else(|| zero.clone(), |v| v.sum_net.with_scale(2)),
+                            gross: value.map_or_else(|| zero.clone(), |v| v.sum_gross.with_scale(2)),
+                        }
+                    })
+                    .collect(),
+                name: name(&group),
+                id: group,
+            })
+            .collect();
+        Ok(TimeSeries {
+            interval: query.interval,
+            split: query.split,
+            filter: filter.descriptions.clone(),
+            series,
+        })
+    }
+}
+
+/// Groups beyond these are drawn together as one
+const CHART_GROUPS: usize = 8;
+const CHART_COLOURS: [&str; CHART_GROUPS + 1] = ["#0d6efd", "#fd7e14", "#198754", "#d63384", "#6f42c1", "#ffc107", "#20c997", "#dc3545", "#adb5bd"];
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 300.0;
+/// Room for the axis labels left of and below the bars
+const CHART_LEFT: f64 = 70.0;
+const CHART_BOTTOM: f64 = 30.0;
+const CHART_TOP: f64 = 10.0;
+/// Bars stay narrow even when there are only a few periods
+const CHART_BAR_WIDTH: f64 = 60.0;
+
+struct ChartBar {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    colour: &'static str,
+    title: String,
+}
+
+struct ChartTick {
+    position: f64,
+    label: String,
+}
+
+/// Stacked bars of a time series, drawn as SVG on the server
+struct Chart {
+    title: &'static str,
+    bars: Vec<ChartBar>,
+    /// Amounts along the y axis
+    ticks: Vec<ChartTick>,
+    /// Periods along the x axis
+    labels: Vec<ChartTick>,
+    legend: Vec<(&'static str, String)>,
+}
+
+impl Chart {
+    fn new(time_series: &TimeSeries, title: &'static str, value: fn(&SeriesPoint) -> &BigDecimal) -> Chart {
+        let mut groups: Vec<(String, Vec<f64>)> = vec![];
+        for (index, series) in time_series.series.iter().enumerate() {
+            let values = series.points.iter().map(|point| value(point).to_f64().unwrap_or_default().max(0.0));
+            if index < CHART_GROUPS {
+                groups.push((series.name.clone(), values.collect()));
+            } else if index == CHART_GROUPS {
+                groups.push(("Sonstige".to_string(), values.collect()));
+            } else if let Some((_, other)) = groups.last_mut() {
+                other.iter_mut().zip(values).for_each(|(sum, value)| *sum += value);
+            }
+        }
+        let periods: Vec<&SeriesPoint> = time_series.series.first().map(|series| series.points.iter().collect()).unwrap_or_default();
+
+        // Four steps of 1, 2 or 5 times a power of ten reach up to the highest bar
+        let highest = (0..periods.len()).map(|i| groups.iter().map(|(_, values)| values[i]).sum::<f64>()).fold(0.0, f64::max);
+        let magnitude = 10f64.powf((highest / 4.0).max(1.0).log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .map(|factor| factor * magnitude)
+            .into_iter()
+            .find(|step| step * 4.0 >= highest)
+            .unwrap_or(10.0 * magnitude);
+        let plot_height = CHART_HEIGHT - CHART_TOP - CHART_BOTTOM;
+        let scale = plot_height / (step * 4.0);
+        let ticks = (0..=4)
+            .map(|i| ChartTick {
+                position: CHART_HEIGHT - CHART_BOTTOM - i as f64 * step * scale,
+                label: format!("{}€", i as f64 * step),
+            })
+            .collect();
+
+        let slot = (CHART_WIDTH - CHART_LEFT) / periods.len().max(1) as f64;
+        let bar_width = (slot * 0.8).min(CHART_BAR_WIDTH);
+        let label_every = periods.len().div_ceil(12).max(1);
+        let mut bars = vec![];
+        let mut labels = vec![];
+        for (i, period) in periods.iter().enumerate() {
+            let x = CHART_LEFT + i as f64 * slot;
+            if i % label_every == 0 {
+                labels.push(ChartTick {
+                    position: x + slot / 2.0,
+                    label: period.label.clone(),
+                });
+            }
+            let mut bottom = CHART_HEIGHT - CHART_BOTTOM;
+            for (colour, (name, values)) in CHART_COLOURS.iter().zip(&groups) {
+                if values[i] <= 0.0 {
+                    continue;
+                }
+                let height = values[i] * scale;
+                bottom -= height;
+                bars.push(ChartBar {
+                    x: x + (slot - bar_width) / 2.0,
+                    y: bottom,
+                    width: bar_width,
+                    height,
+                    colour,
+                    title: format!("{} – {}: {:.2}€", period.label, name, values[i]),
+                });
+            }
+        }
+        Chart {
+            title,
+            bars,
+            ticks,
+            labels,
+            legend: CHART_COLOURS.into_iter().zip(groups.into_iter().map(|(name, _)| name)).collect(),
+        }
+    }
+
+    fn width(&self) -> f64 {
+        CHART_WIDTH
+    }
+
+    fn height(&self) -> f64 {
+        CHART_HEIGHT
+    }
+
+    fn left(&self) -> f64 {
+        CHART_LEFT
+    }
+
+    fn bottom(&self) -> f64 {
+        CHART_HEIGHT - CHART_BOTTOM
+    }
+}
+
+/// A choice of interval or split, linking to the summary with it
+struct SeriesLink {
+    label: &'static str,
+    href: String,
+    active: bool,
+}
 
 #[derive(Template)]
 #[template(path = "summary/overview.html")]
 struct SummaryOverview {
-    sums: Vec<CostCentreWithSum>,
+    filter: ActiveFilter,
+    sums: Vec<CostCentreSummary>,
+    cross_tab: CrossTab,
+    series_query: String,
+    intervals: Vec<SeriesLink>,
+    splits: Vec<SeriesLink>,
+    charts: Vec<Chart>,
+}
+
+pub(crate) async fn summary_overview(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Query(query): Query<SummaryQuery>,
+    Query(series_query): Query<SeriesQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let sums = DBCostCentre::get_summary(&filter.filter, &mut conn).await?;
+    let cross_tab = CrossTab::load(&filter.filter, &mut conn).await?;
+    let time_series = TimeSeries::load(series_query, &filter, &mut conn).await?;
+    let charts = match time_series.series.is_empty() {
+        true => vec![],
+        false => vec![
+            Chart::new(&time_series, "Netto", |point| &point.net),
+            Chart::new(&time_series, "Brutto", |point| &point.gross),
+        ],
+    };
+
+    let link = |query: SeriesQuery, label: &'static str| SeriesLink {
+        label,
+        href: format!("/summary{}#zeitverlauf", query.query_string(&filter)),
+        active: query.interval == series_query.interval && query.split == series_query.split,
+    };
+    let intervals = [Interval::Month, Interval::Week]
+        .map(|interval| link(SeriesQuery { interval, ..series_query }, interval.label()))
+        .into();
+    let splits = [SeriesSplit::CostCentre, SeriesSplit::Project, SeriesSplit::Vendor]
+        .map(|split| link(SeriesQuery { split, ..series_query }, split.label()))
+        .into();
+    Ok(HtmlTemplate(SummaryOverview {
+        series_query: series_query.query_string(&filter),
+        filter,
+        sums,
+        cross_tab,
+        intervals,
+        splits,
+        charts,
+    }))
+}
+
+pub(crate) async fn summary_series_json(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Query(query): Query<SummaryQuery>,
+    Query(series_query): Query<SeriesQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    Ok(Json(TimeSeries::load(series_query, &filter, &mut conn).await?))
 }
 
-pub(crate) async fn summary_overview(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
-    let sums = DBCostCentre::get_summary(&mut conn).await?;
-    Ok(HtmlTemplate(SummaryOverview { sums }))
+pub(crate) async fn summary_csv_series(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Query(query): Query<SummaryQuery>,
+    Query(series_query): Query<SeriesQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let time_series = TimeSeries::load(series_query, &filter, &mut conn).await?;
+
+    let mut wtr = csv::WriterBuilder::new().from_writer(vec![]);
+    let (group_column, group_name) = match series_query.split {
+        SeriesSplit::CostCentre => ("kostenstelle", "cost-centres"),
+        SeriesSplit::Project => ("projekt", "projects"),
+        SeriesSplit::Vendor => ("haendler", "vendors"),
+    };
+    wtr.write_record(["periode_start", "periode", group_column, "summe_netto", "summe_brutto"])?;
+    for series in &time_series.series {
+        for point in &series.points {
+            wtr.write_record([
+                point.period.to_string(),
+                point.label.clone(),
+                series.name.clone(),
+                point.net.to_string(),
+                point.gross.to_string(),
+            ])?;
+        }
+    }
+
+    let interval_name = match series_query.interval {
+        Interval::Month => "monthly",
+        Interval::Week => "weekly",
+    };
+    filter.csv_response(&format!("{}-{}", interval_name, group_name), wtr)
 }
 
-pub(crate) async fn summary_csv_aggregated(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
-    let sums = DBCostCentre::get_summary(&mut conn).await?;
+#[derive(Template)]
+#[template(path = "summary/cross_tab.html")]
+struct SummaryCrossTab {
+    filter: ActiveFilter,
+    cross_tab: CrossTab,
+}
+
+pub(crate) async fn summary_cross_tab(DatabaseConnection(mut conn): DatabaseConnection, Query(query): Query<SummaryQuery>) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let cross_tab = CrossTab::load(&filter.filter, &mut conn).await?;
+    Ok(HtmlTemplate(SummaryCrossTab { filter, cross_tab }))
+}
+
+pub(crate) async fn summary_csv_cross_tab(DatabaseConnection(mut conn): DatabaseConnection, Query(query): Query<SummaryQuery>) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let cross_tab = CrossTab::load(&filter.filter, &mut conn).await?;
 
     let mut wtr = csv::WriterBuilder::new().from_writer(vec![]);
-    wtr.write_record(["kostenstelle", "mwst_satz", "summe_netto", "summe_mwst_befreit"])?;
+    let mut header = vec!["projekt".to_string()];
+    for name in cross_tab.cost_centres.iter().map(|cc| cc.name.as_str()).chain(["gesamt"]) {
+        header.extend(["netto", "mwst", "brutto"].map(|value| format!("{} {}", name, value)));
+    }
+    wtr.write_record(&header)?;
+
+    let write_row = |wtr: &mut csv::Writer<Vec<u8>>, name: &str, cells: Vec<Sums>| {
+        let mut record = vec![name.to_string()];
+        for sums in cells {
+            record.extend([sums.net.to_string(), sums.vat.to_string(), sums.gross.to_string()]);
+        }
+        wtr.write_record(&record)
+    };
+    for project in &cross_tab.projects {
+        let cells = cross_tab.cost_centres.iter().map(|cc| cross_tab.cell(project.id, cc.id));
+        write_row(&mut wtr, &project.name, cells.chain([cross_tab.project_total(project.id)]).collect())?;
+    }
+    let totals = cross_tab.cost_centres.iter().map(|cc| cross_tab.cost_centre_total(cc.id));
+    write_row(&mut wtr, "gesamt", totals.chain([cross_tab.total.clone()]).collect())?;
+
+    filter.csv_response("projects-cost-centres", wtr)
+}
 
-    for record in sums {
+#[derive(Template)]
+#[template(path = "summary/project.html")]
+struct SummaryProject {
+    project: DBProject,
+    sums: Vec<CostCentreSummary>,
+    total: Sums,
+    without_cost_centre: Sums,
+}
+
+/// Final cost breakdown of a single project
+pub(crate) async fn summary_project(DatabaseConnection(mut conn): DatabaseConnection, Path(project_id): Path<i64>) -> Result<impl IntoResponse, AppError> {
+    let project = DBProject::get_by_id(project_id, &mut conn).await?;
+    let filter = SummaryFilter {
+        project_id: Some(project_id),
+        ..SummaryFilter::default()
+    };
+    let sums = DBCostCentre::get_summary(&filter, &mut conn).await?;
+    let cross_tab = CrossTab::load(&filter, &mut conn).await?;
+    Ok(HtmlTemplate(SummaryProject {
+        project,
+        sums,
+        total: cross_tab.project_total(Some(project_id)),
+        without_cost_centre: cross_tab.cell(Some(project_id), None),
+    }))
+}
+
+pub(crate) async fn summary_csv_aggregated(DatabaseConnection(mut conn): DatabaseConnection, Query(query): Query<SummaryQuery>) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let sums = DBCostCentre::get_summary(&filter.filter, &mut conn).await?;
+
+    let mut wtr = filter.csv_writer()?;
+    // Every cost centre gets a subtotal row (without VAT rate) followed by its sums per VAT rate, both including the cost centres below it
+    wtr.write_record(["kostenstelle", "ebene", "mwst_satz", "summe_netto", "summe_mwst_befreit"])?;
+
+    for summary in sums {
+        let depth = summary.cost_centre.depth().to_string();
         wtr.write_record([
-            record.cost_centre_name,
-            record.vat.to_string(),
-            record.sum_net.to_string(),
-            record.sum_vat_exempted.to_string(),
+            summary.cost_centre.path.as_str(),
+            &depth,
+            "",
+            &summary.sum_net.to_string(),
+            &summary.sum_vat_exempted.to_string(),
         ])?;
+        for record in summary.sums {
+            wtr.write_record([
+                summary.cost_centre.path.as_str(),
+                &depth,
+                &record.vat.to_string(),
+                &record.sum_net.to_string(),
+                &record.sum_vat_exempted.to_string(),
+            ])?;
+        }
     }
 
-    let csv_string = String::from_utf8(wtr.into_inner()?)?;
-    Ok((
-        [
-            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
-            (header::CONTENT_DISPOSITION, "attachment; filename=\"berechenbarkeit-aggregated.csv\""),
-        ],
-        csv_string,
-    ))
+    filter.csv_response("aggregated", wtr)
 }
 
-pub(crate) async fn summary_csv_raw(DatabaseConnection(mut conn): DatabaseConnection) -> Result<impl IntoResponse, AppError> {
-    let items = DBInvoiceItem::get_all(&mut conn).await?;
+pub(crate) async fn summary_csv_raw(DatabaseConnection(mut conn): DatabaseConnection, Query(query): Query<SummaryQuery>) -> Result<impl IntoResponse, AppError> {
+    let filter = ActiveFilter::load(query, &mut conn).await?;
+    let items = DBInvoiceItem::get_all(&filter.filter, &mut conn).await?;
 
-    let mut wtr = csv::WriterBuilder::new().from_writer(vec![]);
+    let mut wtr = filter.csv_writer()?;
     wtr.write_record([
         "haendler",
         "rechnungsdatum",
         "rechnungsnummer",
+        "zahlungsart",
+        "manuell_erfasst",
         "typ",
+        "artikelnummer",
+        "ean",
         "beschreibung",
         "menge",
+        "verpackungseinheit",
         "einzelpreis_netto",
         "gesamtpreis_netto",
         "mwst_satz",
         "mwst_befreit",
         "kostenstelle",
+        "projekt",
     ])?;
 
     for record in items {
@@ -67,26 +719,28 @@ pub(crate) async fn summary_csv_raw(DatabaseConnection(mut conn): DatabaseConnec
             record.invoice_vendor,
             record.invoice_date.to_string(),
             record.invoice_number,
+            record.invoice_payment_type.unwrap_or_default(),
+            match record.invoice_manual {
+                true => "true".to_string(),
+                false => "false".to_string(),
+            },
             record.typ,
+            record.article_number.unwrap_or_default(),
+            record.ean.unwrap_or_default(),
             record.description,
             record.amount.to_string(),
+            record.packaging_unit_amount.map(|pu_amount| pu_amount.to_string()).unwrap_or_default(),
             record.net_price_single.to_string(),
-            (record.net_price_single * record.amount).to_string(),
+            (record.net_price_single * record.amount).round(2).to_string(),
             record.vat.to_string(),
             match record.vat_exempt {
                 true => "true".to_string(),
                 false => "false".to_string(),
             },
             record.cost_centre.unwrap_or_else(|| "".to_string()),
+            record.project.unwrap_or_default(),
         ])?;
     }
 
-    let csv_string = String::from_utf8(wtr.into_inner()?)?;
-    Ok((
-        [
-            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
-            (header::CONTENT_DISPOSITION, "attachment; filename=\"berechenbarkeit-raw.csv\""),
-        ],
-        csv_string,
-    ))
+    filter.csv_response("raw", wtr)
 }
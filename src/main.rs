@@ -4,14 +4,18 @@ use axum::extract::{DefaultBodyLimit, MatchedPath};
 use axum::http::Request;
 use axum::response::{Html, Response};
 use axum::routing::{delete, get, post, put};
+use axum::Extension;
 use axum::{http::StatusCode, response::IntoResponse, Router};
 use clap::Parser;
-use sqlx::postgres::PgPoolOptions;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::net::SocketAddr;
+use std::time::Duration;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing::info_span;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use utils::{AdminUsers, TrustedProxies};
 
 mod config;
 mod db;
@@ -27,12 +31,20 @@ async fn main() {
 
     let config = Config::parse();
 
+    if let Err(e) = berechenbarkeit_lib::vendors::init_regex_vendors(config.vendor_definitions_dir.as_deref()) {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+
     let db_pool = PgPoolOptions::new().connect(&config.database_url).await.expect("sqlx: could not connect to database_url");
 
     // This embeds database migrations in the application binary so we can ensure the database
     // is migrated correctly on startup
     sqlx::migrate!().run(&db_pool).await.expect("sqlx: migration failed");
 
+    tokio::spawn(deactivate_ended_projects(db_pool.clone()));
+    tokio::spawn(remove_abandoned_pending_uploads());
+
     let assets_base_path = match option_env!("BERECHENBARKEIT_STATIC_BASE_PATH") {
         Some(env) => env.to_string(),
         None => "src/assets".to_owned(),
@@ -40,10 +52,21 @@ async fn main() {
 
     let app = Router::new()
         .route("/invoices", get(handlers::invoice::invoice_list))
+        .route("/invoices/trash", get(handlers::invoice::invoice_trash))
         .route("/invoice/upload", post(handlers::invoice::invoice_add_upload))
+        .route("/invoice/upload/confirm", post(handlers::invoice::invoice_upload_confirm))
+        .route("/invoice/upload/cancel", post(handlers::invoice::invoice_upload_cancel))
+        .route("/invoice/new", get(handlers::invoice::invoice_new).post(handlers::invoice::invoice_new_submit))
         .layer(DefaultBodyLimit::max(10 * 1024 * 1024))
         .route("/invoice/:invoice_id/pdf", get(handlers::invoice::download))
-        .route("/invoice/:invoice_id/invoiceitem/:invoiceitem_id/split", post(handlers::invoice::invoice_item_split))
+        .route("/invoice/:invoice_id/xml", get(handlers::invoice::download_xml))
+        .route("/invoice/:invoice_id/document", get(handlers::invoice::download_document))
+        .route("/invoice/:invoice_id/view", get(handlers::invoice::invoice_view))
+        .route("/invoice/:invoice_id/history", get(handlers::history::invoice_history))
+        .route(
+            "/invoice/:invoice_id/invoiceitem/:invoiceitem_id/split",
+            get(handlers::invoice::invoice_item_split_page).post(handlers::invoice::invoice_item_split),
+        )
         .route(
             "/invoice/:invoice_id/edit",
             get(handlers::invoice::invoice_edit).post(handlers::invoice::invoice_edit_submit),
@@ -52,6 +75,8 @@ async fn main() {
             "/invoice/:invoice_id/delete",
             get(handlers::invoice::invoice_delete_confirm).post(handlers::invoice::invoice_delete),
         )
+        .route("/invoice/:invoice_id/restore", post(handlers::invoice::invoice_restore))
+        .route("/invoice/:invoice_id/purge", post(handlers::invoice::invoice_purge))
         .route("/projects", get(handlers::projects::list).post(handlers::projects::add))
         .route(
             "/projects/default",
@@ -62,14 +87,32 @@ async fn main() {
         .route("/projects/new", get(handlers::projects::new_project_page))
         .route("/projects/:id", delete(handlers::projects::delete).put(handlers::projects::update))
         .route("/projects/:id/edit", get(handlers::projects::edit_project_page))
+        .route("/rules", get(handlers::rules::list).post(handlers::rules::add))
+        .route("/rules/new", get(handlers::rules::new_rule_page))
+        .route("/rules/:id", delete(handlers::rules::delete))
+        .route("/rules/:id/edit", get(handlers::rules::edit_rule_page).post(handlers::rules::update))
+        .route("/rules/:id/preview", get(handlers::rules::preview))
+        .route("/rules/:id/apply", post(handlers::rules::apply))
+        .route("/budgets", get(handlers::budgets::list).post(handlers::budgets::add))
+        .route("/budgets/new", get(handlers::budgets::new_budget_page))
+        .route("/budgets/:id", delete(handlers::budgets::delete))
+        .route("/budgets/:id/edit", get(handlers::budgets::edit_budget_page).post(handlers::budgets::update))
         .route("/cost_centres", get(handlers::cost_centre::cost_centre_list).post(handlers::cost_centre::cost_centre_add))
         .route("/cost_centre/:cost_centre_id", put(handlers::cost_centre::update))
         .route("/cost_centre/:cost_centre_id/delete", get(handlers::cost_centre::cost_centre_delete))
+        .route("/history", get(handlers::history::history))
         .route("/summary", get(handlers::summary::summary_overview))
         .route("/summary/aggregated_csv", get(handlers::summary::summary_csv_aggregated))
         .route("/summary/raw_csv", get(handlers::summary::summary_csv_raw))
+        .route("/summary/series_csv", get(handlers::summary::summary_csv_series))
+        .route("/summary/series_json", get(handlers::summary::summary_series_json))
+        .route("/summary/projects", get(handlers::summary::summary_cross_tab))
+        .route("/summary/projects_csv", get(handlers::summary::summary_csv_cross_tab))
+        .route("/summary/project/:project_id", get(handlers::summary::summary_project))
         .route("/", get(handlers::home::home))
         .with_state(db_pool)
+        .layer(Extension(TrustedProxies(config.trusted_proxies.into())))
+        .layer(Extension(AdminUsers(config.admin_users.into())))
         .layer(TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
             // Log the matched route's path (with placeholders not filled in).
             // Use request.uri() or OriginalUri if you want the real path.
@@ -87,7 +130,38 @@ async fn main() {
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
 
     tracing::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+}
+
+/// Deactivates projects whose end has passed, on startup and then once an hour
+async fn deactivate_ended_projects(db_pool: PgPool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        let deactivated = async {
+            let mut conn = db_pool.acquire().await?;
+            // Nobody in particular made this change
+            db::util::set_author("", &mut conn).await?;
+            db::projects::DBProject::deactivate_ended(&mut conn).await
+        }
+        .await;
+        match deactivated {
+            Ok(names) => names.iter().for_each(|name| tracing::info!("Deactivated project {} as its end has passed", name)),
+            Err(e) => tracing::error!("Could not deactivate ended projects: {}", e),
+        }
+    }
+}
+
+/// Removes uploads held back as possible duplicates which were neither confirmed nor cancelled, once an hour
+async fn remove_abandoned_pending_uploads() {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match handlers::invoice::remove_abandoned_pending_uploads() {
+            Ok(paths) => paths.iter().for_each(|path| tracing::info!("Removed abandoned pending upload {}", path.display())),
+            Err(e) => tracing::error!("Could not remove abandoned pending uploads: {}", e),
+        }
+    }
 }
 
 struct AppError(anyhow::Error);
@@ -1,9 +1,62 @@
-use axum::http::HeaderMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{request::Parts, HeaderMap};
 
 use axum_core::response::IntoResponse;
 
 use crate::AppError;
 
+/// Request headers naming the logged in user, as set by an authenticating reverse proxy, checked in this order
+const USER_HEADERS: [&str; 2] = ["Remote-User", "X-Forwarded-User"];
+
+fn request_user(request_headers: &HeaderMap) -> Option<&str> {
+    USER_HEADERS
+        .iter()
+        .find_map(|header| request_headers.get(*header))
+        .and_then(|value| value.to_str().ok())
+        .filter(|user| !user.is_empty())
+}
+
+/// Addresses of the reverse proxies trusted to name the logged in user, made available to requests as an extension
+#[derive(Clone)]
+pub struct TrustedProxies(pub Arc<[IpAddr]>);
+
+/// Users allowed to purge invoices, made available to requests as an extension
+#[derive(Clone)]
+pub struct AdminUsers(pub Arc<[String]>);
+
+/// The logged in user, as named by the authenticating reverse proxy.
+/// Anyone can send the user headers, so they are ignored on requests that did not come from a trusted proxy.
+pub struct RequestUser(pub Option<String>);
+
+impl RequestUser {
+    pub fn from_parts(parts: &Parts) -> Self {
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(address)| address.ip().to_canonical());
+        let trusted = parts
+            .extensions
+            .get::<TrustedProxies>()
+            .is_some_and(|TrustedProxies(proxies)| peer.is_some_and(|peer| proxies.contains(&peer)));
+        Self(trusted.then(|| request_user(&parts.headers)).flatten().map(str::to_owned))
+    }
+
+    pub fn is_admin(&self, AdminUsers(admins): &AdminUsers) -> bool {
+        self.0.as_ref().is_some_and(|user| admins.iter().any(|admin| admin.trim() == user))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
 pub fn make_htmx_redirect(request_headers: HeaderMap, target: &str) -> Result<impl IntoResponse, AppError> {
     let mut headers = HeaderMap::new();
     let header_name: &str = request_headers.get("HX-Request").filter(|v| *v == "true").map_or_else(|| "Location", |_| "HX-Location");